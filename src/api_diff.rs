@@ -0,0 +1,242 @@
+//! Public API diffs between the working tree and the last published release of a crate
+use crate::cargo_util::cargo_home;
+use crate::config::WorkspaceConfig;
+use crate::git::{git, toplevel};
+use crate::registry::registry_entry;
+use crate::state::BumpLevel;
+use anyhow::{bail, Context};
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    semver::Version,
+    Package,
+};
+use public_api::{diff::PublicApiDiff, Options, PublicApi};
+use std::path::PathBuf;
+use tokio::process::Command;
+
+pub struct ApiDiff {
+    /// The published version the working tree was compared against
+    pub baseline: Version,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<(String, String)>,
+}
+
+impl ApiDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Removed or changed items break downstream users, added ones only extend the API
+    pub fn is_breaking(&self) -> bool {
+        !self.removed.is_empty() || !self.changed.is_empty()
+    }
+
+    /// The bump these changes require on top of the baseline release
+    pub fn required_bump(&self) -> BumpLevel {
+        BumpLevel::for_change(&self.baseline, self.is_breaking(), !self.added.is_empty())
+    }
+}
+
+/// Build rustdoc JSON for the working tree and the last published version, then diff the two
+///
+/// Everything is built under `<target>/easy-release` so repeat runs can reuse the artifacts.
+pub async fn collect_api_diff(
+    package: &Package,
+    index: &str,
+    config: &WorkspaceConfig,
+    workspace_root: &Utf8Path,
+    target_dir: &Utf8Path,
+) -> anyhow::Result<ApiDiff> {
+    let scratch = target_dir.join("easy-release");
+    let lib_name = lib_name(package)?;
+
+    let baseline = last_published_version(package, index).await?;
+    let baseline_manifest =
+        published_manifest(package, &baseline, config, workspace_root, &scratch).await?;
+
+    let new_json =
+        build_rustdoc_json(&package.manifest_path, &lib_name, &scratch.join("current")).await?;
+    let old_json =
+        build_rustdoc_json(&baseline_manifest, &lib_name, &scratch.join("baseline")).await?;
+
+    // Parsing rustdoc JSON for a big crate takes a while, so keep it off the UI thread
+    tokio::task::spawn_blocking(move || {
+        let old = PublicApi::from_rustdoc_json(old_json, Options::default())?;
+        let new = PublicApi::from_rustdoc_json(new_json, Options::default())?;
+        let diff = PublicApiDiff::between(old, new);
+
+        Ok(ApiDiff {
+            baseline,
+            added: diff.added.iter().map(ToString::to_string).collect(),
+            removed: diff.removed.iter().map(ToString::to_string).collect(),
+            changed: diff
+                .changed
+                .iter()
+                .map(|item| (item.old.to_string(), item.new.to_string()))
+                .collect(),
+        })
+    })
+    .await?
+}
+
+/// The name rustdoc uses for the crate's library target
+fn lib_name(package: &Package) -> anyhow::Result<String> {
+    let lib = package
+        .targets
+        .iter()
+        .find(|target| {
+            target
+                .kind
+                .iter()
+                .any(|kind| kind == "lib" || kind == "rlib" || kind == "proc-macro")
+        })
+        .with_context(|| format!("{} has no library target", package.name))?;
+
+    Ok(lib.name.replace('-', "_"))
+}
+
+/// The newest version published to the sparse `index` that isn't newer than the working copy
+pub async fn last_published_version(package: &Package, index: &str) -> anyhow::Result<Version> {
+    let published = match registry_entry(index, &package.name).await {
+        Ok(entry) => entry
+            .versions
+            .iter()
+            .filter(|v| !v.yanked)
+            .map(|v| v.version.clone())
+            .collect(),
+
+        // Offline, so fall back to whatever's sitting in the registry cache
+        Err(_) => cached_crate_files(&package.name)
+            .into_iter()
+            .map(|(version, _)| version)
+            .collect::<Vec<_>>(),
+    };
+
+    published
+        .into_iter()
+        .filter(|version| *version <= package.version)
+        .max()
+        .with_context(|| format!("{} has never been published", package.name))
+}
+
+/// Every `.crate` file for this crate in cargo's registry cache, across all registries
+fn cached_crate_files(name: &str) -> Vec<(Version, PathBuf)> {
+    let Ok(registries) = std::fs::read_dir(cargo_home().join("registry").join("cache")) else {
+        return vec![];
+    };
+
+    let prefix = format!("{name}-");
+
+    registries
+        .filter_map(Result::ok)
+        .filter_map(|registry| std::fs::read_dir(registry.path()).ok())
+        .flat_map(|entries| entries.filter_map(Result::ok))
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            let version = file_name.strip_prefix(&prefix)?.strip_suffix(".crate")?;
+            Some((version.parse().ok()?, entry.path()))
+        })
+        .collect()
+}
+
+/// Find the manifest of a published version, unpacking it from the registry cache or checking out
+/// its release tag, named by the workspace's tag pattern, into a worktree
+pub async fn published_manifest(
+    package: &Package,
+    version: &Version,
+    config: &WorkspaceConfig,
+    workspace_root: &Utf8Path,
+    scratch: &Utf8Path,
+) -> anyhow::Result<Utf8PathBuf> {
+    let published_dir = scratch.join("published");
+    let unpacked = published_dir.join(format!("{}-{version}", package.name));
+
+    if unpacked.join("Cargo.toml").exists() {
+        return Ok(unpacked.join("Cargo.toml"));
+    }
+
+    let cached = cached_crate_files(&package.name)
+        .into_iter()
+        .find(|(cached, _)| cached == version);
+
+    if let Some((_, krate)) = cached {
+        std::fs::create_dir_all(&published_dir)?;
+
+        let status = Command::new("tar")
+            .arg("-xzf")
+            .arg(&krate)
+            .arg("-C")
+            .arg(&published_dir)
+            .status()
+            .await?;
+
+        if status.success() {
+            // The unpacked crate sits inside our target dir, so stop cargo from thinking it's a
+            // member of the workspace we're releasing
+            let manifest = unpacked.join("Cargo.toml");
+            let contents = std::fs::read_to_string(&manifest)?;
+            std::fs::write(&manifest, format!("{contents}\n[workspace]\n"))?;
+            return Ok(manifest);
+        }
+    }
+
+    let repo = toplevel(workspace_root).await?;
+    let relative = package.manifest_path.strip_prefix(&repo)?;
+
+    let tag = config.release_tag(&package.name, version);
+    let worktree = scratch.join("tags").join(&tag);
+
+    if worktree.exists()
+        || git(
+            &repo,
+            &[
+                "worktree",
+                "add",
+                "--detach",
+                worktree.as_str(),
+                tag.as_str(),
+            ],
+        )
+        .await
+        .is_ok()
+    {
+        return Ok(worktree.join(relative));
+    }
+
+    bail!(
+        "couldn't find {} {version} in the registry cache or at tag {tag}",
+        package.name
+    )
+}
+
+/// Run rustdoc with JSON output. This still needs a nightly toolchain
+///
+/// Unlike our other cargo invocations this ignores `CARGO`, since picking the nightly toolchain
+/// with `+nightly` only works through rustup's `cargo` proxy.
+async fn build_rustdoc_json(
+    manifest_path: &Utf8Path,
+    lib_name: &str,
+    target_dir: &Utf8Path,
+) -> anyhow::Result<Utf8PathBuf> {
+    let output = Command::new("cargo")
+        .arg("+nightly")
+        .arg("rustdoc")
+        .arg("--lib")
+        .arg("--manifest-path")
+        .arg(manifest_path)
+        .arg("--target-dir")
+        .arg(target_dir)
+        .args(["--", "-Z", "unstable-options", "--output-format", "json"])
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        bail!(
+            "rustdoc failed for {manifest_path}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(target_dir.join("doc").join(format!("{lib_name}.json")))
+}
@@ -1,22 +1,14 @@
 //! Utilities for working with cargo and rust files
+use cargo_toml::{Dependency, DepsSet, Edition, Inheritable, Manifest, Product, Publish};
 use std::{
+    collections::HashSet,
     env, fs,
-    path::{Path, PathBuf},
-    process::Command,
-    str,
+    path::{Component, Path, PathBuf},
 };
 
-use anyhow::bail;
-
 /// How many parent folders are searched for a `Cargo.toml`
 const MAX_ANCESTORS: u32 = 10;
 
-/// Some fields parsed from `cargo metadata` command
-pub struct Metadata {
-    pub workspace_root: PathBuf,
-    pub target_directory: PathBuf,
-}
-
 /// Returns the root of the crate that the command is run from
 ///
 /// If the command is run from the workspace root, this will return the top-level Cargo.toml
@@ -49,35 +41,616 @@ fn contains_manifest(path: &Path) -> bool {
         .unwrap_or(false)
 }
 
-impl Metadata {
-    /// Returns the struct filled from `cargo metadata` output
-    /// TODO @Jon, find a different way that doesn't rely on the cargo metadata command (it's slow)
-    pub fn get() -> anyhow::Result<Self> {
-        let output = Command::new("cargo").args(&["metadata"]).output()?;
+/// Build the metadata `cargo metadata --no-deps` would give us by parsing the member manifests
+/// directly, which skips cargo's resolver and so is much faster
+///
+/// Anything this can't work out on its own, like a field inherited from a workspace it can't
+/// find, is an error, and the caller should fall back to asking cargo.
+pub fn read_workspace(manifest_path: Option<&Path>) -> anyhow::Result<cargo_metadata::Metadata> {
+    let start = match manifest_path {
+        Some(path) => path.to_path_buf(),
+        None => crate_root()?.join("Cargo.toml"),
+    };
+    let start = fs::canonicalize(&start)?;
+    let root_manifest = find_workspace_manifest(&start)?;
+    let root_dir = root_manifest
+        .parent()
+        .unwrap_or(Path::new("/"))
+        .to_path_buf();
+
+    let root = Manifest::from_path(&root_manifest)?;
+    let workspace = root.workspace.as_ref();
+
+    let mut members = vec![];
+    if root.package.is_some() {
+        members.push(root_manifest.clone());
+    }
 
-        if !output.status.success() {
-            let mut msg = str::from_utf8(&output.stderr).unwrap().trim();
-            if msg.starts_with("error: ") {
-                msg = &msg[7..];
+    if let Some(workspace) = workspace {
+        for manifest in member_manifests(&root_dir, &workspace.members, &workspace.exclude) {
+            if manifest.exists() && !members.contains(&manifest) {
+                members.push(manifest);
             }
+        }
+    }
+
+    let ws_deps = workspace.map(|workspace| &workspace.dependencies);
+    let packages = members
+        .iter()
+        .map(|manifest| package_json(manifest, &root_dir, ws_deps))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let workspace_metadata = match workspace.and_then(|workspace| workspace.metadata.as_ref()) {
+        Some(metadata) => serde_json::to_value(metadata)?,
+        None => serde_json::Value::Null,
+    };
+
+    let target_directory = env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| root_dir.join("target"));
+
+    let ids = packages
+        .iter()
+        .map(|package| package["id"].clone())
+        .collect::<Vec<_>>();
+
+    let meta = serde_json::json!({
+        "workspace_members": ids,
+        "packages": packages,
+        "resolve": null,
+        "workspace_root": root_dir,
+        "target_directory": target_directory,
+        "metadata": workspace_metadata,
+        "version": 1,
+    });
+
+    Ok(serde_json::from_value(meta)?)
+}
 
-            bail!("{}", msg)
+/// The manifest of the workspace that `manifest` belongs to, which may be `manifest` itself
+fn find_workspace_manifest(manifest: &Path) -> anyhow::Result<PathBuf> {
+    let has_workspace = |path: &Path| -> anyhow::Result<bool> {
+        let doc = fs::read_to_string(path)?.parse::<toml::Value>()?;
+        Ok(doc.get("workspace").is_some())
+    };
+
+    if has_workspace(manifest)? {
+        return Ok(manifest.to_path_buf());
+    }
+
+    let mut dir = manifest.parent();
+    for _ in 0..MAX_ANCESTORS {
+        let Some(parent) = dir.and_then(Path::parent) else {
+            break;
+        };
+
+        let candidate = parent.join("Cargo.toml");
+        if candidate.exists() && has_workspace(&candidate)? {
+            return Ok(candidate);
         }
 
-        let stdout = str::from_utf8(&output.stdout).unwrap();
-        if let Some(line) = stdout.lines().next() {
-            let meta: serde_json::Value = serde_json::from_str(line)?;
+        dir = Some(parent);
+    }
 
-            let workspace_root = meta["workspace_root"].to_string().into();
+    // A package on its own is its own workspace
+    Ok(manifest.to_path_buf())
+}
 
-            let target_directory = meta["target_directory"].to_string().into();
+/// Where every directory the root manifest's member globs match keeps its `Cargo.toml`, whether
+/// or not it has one yet
+///
+/// This is what the watcher checks for new members, since a new `crates/foo` only shows up here
+pub fn workspace_member_manifests(workspace_root: &Path) -> Vec<PathBuf> {
+    let Ok(root) = Manifest::from_path(workspace_root.join("Cargo.toml")) else {
+        return vec![];
+    };
 
-            return Ok(Self {
-                workspace_root,
-                target_directory,
-            });
+    match root.workspace {
+        Some(workspace) => member_manifests(workspace_root, &workspace.members, &workspace.exclude),
+        None => vec![],
+    }
+}
+
+/// The manifest paths of the directories matched by `members`, leaving out the `exclude`d ones
+fn member_manifests(root_dir: &Path, members: &[String], exclude: &[String]) -> Vec<PathBuf> {
+    let excluded = exclude
+        .iter()
+        .map(|dir| normalize(&root_dir.join(dir)))
+        .collect::<Vec<_>>();
+
+    members
+        .iter()
+        .flat_map(|pattern| expand_member_glob(root_dir, pattern))
+        .filter(|dir| !excluded.iter().any(|excluded| dir.starts_with(excluded)))
+        .map(|dir| dir.join("Cargo.toml"))
+        .collect()
+}
+
+/// The directories a `workspace.members` entry like `crates/*` refers to
+fn expand_member_glob(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut dirs = vec![root.to_path_buf()];
+
+    for component in pattern.split('/').filter(|component| !component.is_empty()) {
+        if !component.contains('*') {
+            dirs = dirs.into_iter().map(|dir| dir.join(component)).collect();
+            continue;
         }
 
-        anyhow::bail!("Failed to parse cargo metadata")
+        dirs = dirs
+            .iter()
+            .filter_map(|dir| fs::read_dir(dir).ok())
+            .flat_map(|entries| entries.filter_map(Result::ok))
+            .filter(|entry| entry.path().is_dir())
+            .filter(|entry| matches_pattern(component, &entry.file_name().to_string_lossy()))
+            .map(|entry| entry.path())
+            .collect();
+        dirs.sort();
+    }
+
+    dirs
+}
+
+/// One package in the shape `cargo metadata` prints it
+fn package_json(
+    manifest_path: &Path,
+    root_dir: &Path,
+    ws_deps: Option<&DepsSet>,
+) -> anyhow::Result<serde_json::Value> {
+    let manifest = Manifest::from_path(manifest_path)?;
+    let package = manifest
+        .package
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("{} has no [package]", manifest_path.display()))?;
+    let dir = manifest_path.parent().unwrap_or(Path::new("/"));
+
+    let version = package.version.get()?;
+    let edition = match package.edition.get()? {
+        Edition::E2015 => "2015",
+        Edition::E2018 => "2018",
+        Edition::E2021 => "2021",
+    };
+    let optional = |field: &Option<Inheritable<String>>| -> anyhow::Result<Option<String>> {
+        Ok(match field {
+            Some(value) => Some(value.get()?.clone()),
+            None => None,
+        })
+    };
+    let publish = match package.publish.get()? {
+        Publish::Flag(true) => None,
+        Publish::Flag(false) => Some(vec![]),
+        Publish::Registry(registries) => Some(registries.clone()),
+    };
+    let license_file = match &package.license_file {
+        Some(path) => Some(path.get()?.clone()),
+        None => None,
+    };
+
+    let mut dependencies = vec![];
+    let mut add_deps = |deps: &DepsSet, kind: Option<&str>, target: Option<&str>| {
+        let context = DependencyContext {
+            kind,
+            target,
+            dir,
+            root_dir,
+            ws_deps,
+        };
+        for (key, dep) in deps {
+            dependencies.push(dependency_json(key, dep, &context)?);
+        }
+        anyhow::Ok(())
+    };
+
+    add_deps(&manifest.dependencies, None, None)?;
+    add_deps(&manifest.dev_dependencies, Some("dev"), None)?;
+    add_deps(&manifest.build_dependencies, Some("build"), None)?;
+    for (platform, target) in &manifest.target {
+        add_deps(&target.dependencies, None, Some(platform))?;
+        add_deps(&target.dev_dependencies, Some("dev"), Some(platform))?;
+        add_deps(&target.build_dependencies, Some("build"), Some(platform))?;
+    }
+
+    // Cargo gives every optional dependency a feature of its own, unless a feature refers to it
+    // with `dep:`
+    let mut features = manifest.features.clone();
+    let referenced = features
+        .values()
+        .flatten()
+        .filter_map(|feature| feature.strip_prefix("dep:"))
+        .map(str::to_string)
+        .collect::<HashSet<_>>();
+    for dep in dependencies.iter().filter(|dep| dep["optional"] == true) {
+        let key = dep["rename"]
+            .as_str()
+            .or(dep["name"].as_str())
+            .unwrap_or_default();
+        if !referenced.contains(key) {
+            features
+                .entry(key.to_string())
+                .or_insert_with(|| vec![format!("dep:{key}")]);
+        }
+    }
+
+    let target = |name: &str, kind: &str, path: &Path| {
+        serde_json::json!({
+            "name": name,
+            "kind": [kind],
+            "crate_types": [kind],
+            "src_path": normalize(&dir.join(path)),
+            "edition": edition,
+        })
+    };
+    let mut targets = vec![];
+
+    // Library names are always snake case, whatever the package is called
+    let lib_path = Path::new("src/lib.rs");
+    match &manifest.lib {
+        Some(lib) => {
+            let name = lib.name.clone().unwrap_or_else(|| package.name.clone());
+            let kind = if lib.proc_macro { "proc-macro" } else { "lib" };
+            let path = lib.path.as_deref().map(Path::new).unwrap_or(lib_path);
+            targets.push(target(&name.replace('-', "_"), kind, path));
+        }
+        None if dir.join(lib_path).exists() => {
+            targets.push(target(&package.name.replace('-', "_"), "lib", lib_path));
+        }
+        None => {}
+    }
+
+    for (name, path) in bin_targets(dir, &package.name, &manifest.bin) {
+        targets.push(target(&name, "bin", &path));
+    }
+
+    Ok(serde_json::json!({
+        "name": package.name,
+        "version": version,
+        "id": package_id(dir, &package.name, version),
+        "authors": package.authors.get()?,
+        "source": null,
+        "description": optional(&package.description)?,
+        "dependencies": dependencies,
+        "license": optional(&package.license)?,
+        "license_file": license_file,
+        "targets": targets,
+        "features": features,
+        "manifest_path": manifest_path,
+        "categories": package.categories.get()?,
+        "keywords": package.keywords.get()?,
+        "repository": optional(&package.repository)?,
+        "homepage": optional(&package.homepage)?,
+        "documentation": optional(&package.documentation)?,
+        "edition": edition,
+        "metadata": package.metadata,
+        "links": package.links,
+        "publish": publish,
+        "rust_version": optional(&package.rust_version)?,
+    }))
+}
+
+/// A path package's id, in the `path+file:///dir#name@version` form cargo has used since 1.77.
+/// The name is left out when it's the same as the directory's
+fn package_id(dir: &Path, name: &str, version: &str) -> String {
+    let url = format!("path+file://{}", dir.display());
+
+    match dir.file_name().and_then(|dir| dir.to_str()) == Some(name) {
+        true => format!("{url}#{version}"),
+        false => format!("{url}#{name}@{version}"),
+    }
+}
+
+/// The binaries in a package, following cargo's rules for where a `[[bin]]` without a `path`
+/// lives and which files under `src/bin` count as binaries of their own
+fn bin_targets(dir: &Path, package: &str, declared: &[Product]) -> Vec<(String, PathBuf)> {
+    let main = Path::new("src/main.rs");
+    let mut bins = vec![];
+
+    for bin in declared {
+        let name = bin.name.clone().unwrap_or_else(|| package.to_string());
+        let path = match &bin.path {
+            Some(path) => PathBuf::from(path),
+            None if name == package && dir.join(main).exists() => main.to_path_buf(),
+            None if dir.join(format!("src/bin/{name}.rs")).exists() => {
+                PathBuf::from(format!("src/bin/{name}.rs"))
+            }
+            None => PathBuf::from(format!("src/bin/{name}/main.rs")),
+        };
+        bins.push((name, path));
+    }
+
+    if declared.is_empty() && dir.join(main).exists() {
+        bins.push((package.to_string(), main.to_path_buf()));
+    }
+
+    // Both `src/bin/<name>.rs` and `src/bin/<name>/main.rs` are picked up automatically
+    let mut discovered = fs::read_dir(dir.join("src/bin"))
+        .into_iter()
+        .flatten()
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            let relative = path.strip_prefix(dir).ok()?.to_path_buf();
+            match path.extension().and_then(|ext| ext.to_str()) {
+                Some("rs") => Some((path.file_stem()?.to_str()?.to_string(), relative)),
+                _ if path.join("main.rs").exists() => Some((
+                    path.file_name()?.to_str()?.to_string(),
+                    relative.join("main.rs"),
+                )),
+                _ => None,
+            }
+        })
+        .collect::<Vec<_>>();
+    discovered.sort();
+
+    for (name, path) in discovered {
+        let known = bins
+            .iter()
+            .any(|(known, known_path)| *known == name || *known_path == path);
+        if !known {
+            bins.push((name, path));
+        }
+    }
+
+    bins
+}
+
+/// Where a dependency was declared, which decides how `cargo metadata` describes it
+struct DependencyContext<'a> {
+    kind: Option<&'a str>,
+    target: Option<&'a str>,
+    dir: &'a Path,
+    root_dir: &'a Path,
+    ws_deps: Option<&'a DepsSet>,
+}
+
+/// One dependency in the shape `cargo metadata` prints it, filling in `workspace = true` entries
+/// from `[workspace.dependencies]`
+fn dependency_json(
+    key: &str,
+    dep: &Dependency,
+    context: &DependencyContext,
+) -> anyhow::Result<serde_json::Value> {
+    // Paths in `[workspace.dependencies]` are relative to the workspace root
+    let (dep, features, optional, base) = match dep {
+        Dependency::Inherited(inherited) => {
+            let ws_dep = context
+                .ws_deps
+                .and_then(|deps| deps.get(key))
+                .ok_or_else(|| anyhow::anyhow!("{key} isn't in [workspace.dependencies]"))?;
+            let mut features = ws_dep.req_features().to_vec();
+            features.extend(inherited.features.iter().cloned());
+            let optional = inherited.optional || ws_dep.optional();
+            (ws_dep, features, optional, context.root_dir)
+        }
+        dep => (
+            dep,
+            dep.req_features().to_vec(),
+            dep.optional(),
+            context.dir,
+        ),
+    };
+
+    let detail = dep.detail();
+    let path = detail
+        .and_then(|detail| detail.path.as_ref())
+        .map(|path| normalize(&base.join(path)));
+    let registry = match detail.and_then(|detail| detail.registry.as_ref()) {
+        Some(name) => Some(registry_index(context.root_dir, name)?),
+        None => None,
+    };
+
+    let source = match (detail, dep.git(), &path, &registry) {
+        (Some(detail), Some(git), _, _) => {
+            let query = [
+                ("branch", &detail.branch),
+                ("tag", &detail.tag),
+                ("rev", &detail.rev),
+            ]
+            .into_iter()
+            .find_map(|(key, value)| Some(format!("?{key}={}", value.as_ref()?)));
+            Some(format!("git+{git}{}", query.unwrap_or_default()))
+        }
+        (_, _, Some(_), _) => None,
+        (_, _, None, Some(index)) if index.starts_with("sparse+") => Some(index.clone()),
+        (_, _, None, Some(index)) => Some(format!("registry+{index}")),
+        _ => Some(crate::registry::CRATES_IO.to_string()),
+    };
+    let (name, rename) = match dep.package() {
+        Some(package) => (package.to_string(), Some(key.to_string())),
+        None => (key.to_string(), None),
+    };
+
+    Ok(serde_json::json!({
+        "name": name,
+        "source": source,
+        "req": dep.req(),
+        "kind": context.kind,
+        "optional": optional,
+        "uses_default_features": detail.map(|detail| detail.default_features).unwrap_or(true),
+        "features": features,
+        "target": context.target,
+        "rename": rename,
+        "registry": registry,
+        "path": path,
+    }))
+}
+
+/// The index URL of a registry named in a dependency or by `--registry`, looked up the way cargo
+/// does: the `CARGO_REGISTRIES_<NAME>_INDEX` variable, then `.cargo/config.toml` in the workspace
+/// and its parents, then cargo's home directory
+pub fn registry_index(root_dir: &Path, name: &str) -> anyhow::Result<String> {
+    let var = format!(
+        "CARGO_REGISTRIES_{}_INDEX",
+        name.to_uppercase().replace('-', "_")
+    );
+    if let Ok(index) = env::var(var) {
+        return Ok(index);
+    }
+
+    let home = cargo_home();
+    let configs = root_dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(std::iter::once(home))
+        .flat_map(|dir| [dir.join("config.toml"), dir.join("config")]);
+
+    for config in configs {
+        let Ok(contents) = fs::read_to_string(&config) else {
+            continue;
+        };
+
+        let config = contents.parse::<toml::Value>()?;
+        let index = config
+            .get("registries")
+            .and_then(|registries| registries.get(name))
+            .and_then(|registry| registry.get("index"))
+            .and_then(|index| index.as_str());
+
+        if let Some(index) = index {
+            return Ok(index.to_string());
+        }
+    }
+
+    anyhow::bail!("no index configured for registry `{name}`")
+}
+
+/// Resolve `.` and `..` in a path without touching the filesystem, so paths come out the way
+/// cargo prints them
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+
+    normalized
+}
+
+/// Match a name against a pattern where `*` stands for any run of characters
+pub fn matches_pattern(pattern: &str, name: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all, so the name has to match exactly
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(idx) => rest = &rest[idx + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+/// Returns the cargo binary to run
+///
+/// When we're launched as `cargo easy-release`, cargo tells us which binary invoked us through
+/// `CARGO`, so we stick to that toolchain rather than whatever is first on `PATH`.
+pub fn cargo_bin() -> PathBuf {
+    env::var_os("CARGO")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("cargo"))
+}
+
+/// Returns cargo's home directory, where the registry cache lives
+pub fn cargo_home() -> PathBuf {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")))
+        .unwrap_or_else(|| PathBuf::from(".cargo"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cargo_metadata::{Metadata, MetadataCommand};
+    use serde_json::{json, Value};
+
+    /// The parts of the metadata the app reads, in an order that doesn't depend on who built it
+    fn comparable(meta: &Metadata) -> Value {
+        let mut members = meta
+            .workspace_members
+            .iter()
+            .map(|id| &id.repr)
+            .collect::<Vec<_>>();
+        members.sort();
+
+        let mut packages = meta
+            .workspace_packages()
+            .into_iter()
+            .map(|package| {
+                let mut dependencies = package
+                    .dependencies
+                    .iter()
+                    .map(|dep| serde_json::to_value(dep).unwrap())
+                    .collect::<Vec<_>>();
+                dependencies.sort_by_key(|dep| dep.to_string());
+
+                let mut targets = package
+                    .targets
+                    .iter()
+                    .filter(|target| {
+                        let kinds = ["lib", "bin", "proc-macro"];
+                        target
+                            .kind
+                            .iter()
+                            .any(|kind| kinds.contains(&kind.as_str()))
+                    })
+                    .map(|target| json!([target.name, target.kind, target.src_path]))
+                    .collect::<Vec<_>>();
+                targets.sort_by_key(|target| target.to_string());
+
+                json!({
+                    "name": package.name,
+                    "version": package.version,
+                    "id": package.id.repr,
+                    "manifest_path": package.manifest_path,
+                    "description": package.description,
+                    "license": package.license,
+                    "publish": package.publish,
+                    "edition": package.edition,
+                    "features": package.features,
+                    "dependencies": dependencies,
+                    "targets": targets,
+                })
+            })
+            .collect::<Vec<_>>();
+        packages.sort_by_key(|package| package["name"].to_string());
+
+        json!({
+            "workspace_root": meta.workspace_root,
+            "workspace_members": members,
+            "packages": packages,
+        })
+    }
+
+    #[test]
+    fn matches_cargo_metadata() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/workspace");
+        let root = fs::canonicalize(root).unwrap();
+
+        // Cargo only reads the fixture's `.cargo/config.toml` from inside it
+        let expected = MetadataCommand::new()
+            .current_dir(&root)
+            .manifest_path(root.join("Cargo.toml"))
+            .no_deps()
+            .exec()
+            .unwrap();
+        let actual = read_workspace(Some(&root.join("Cargo.toml"))).unwrap();
+
+        assert_eq!(comparable(&actual), comparable(&expected));
     }
 }
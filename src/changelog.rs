@@ -0,0 +1,343 @@
+//! Changelog sections generated from the conventional commits since a crate's last release
+use crate::git::{self, Commit};
+use crate::state::{BumpLevel, CrateGraph};
+use cargo_metadata::{camino::Utf8PathBuf, semver::Version, Package, PackageId};
+use serde::Serialize;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const CHANGELOG_FILE: &str = "CHANGELOG.md";
+
+/// Section headings in the order they're written, keyed by commit type
+const GROUPS: &[(&str, &str)] = &[
+    ("feat", "Features"),
+    ("fix", "Bug Fixes"),
+    ("perf", "Performance"),
+    ("refactor", "Refactoring"),
+    ("docs", "Documentation"),
+    ("other", "Other"),
+];
+
+/// Commit types that don't say anything to users of the crate
+const SKIPPED: &[&str] = &["chore", "ci", "build", "test", "style"];
+
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct ChangelogEntry {
+    pub hash: String,
+    pub kind: String,
+    pub scope: Option<String>,
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl ChangelogEntry {
+    /// Read a `type(scope)!: description` subject. Anything else lands in "Other"
+    pub fn parse(commit: &Commit) -> Self {
+        let breaking_footer = commit.body.lines().any(|line| {
+            line.starts_with("BREAKING CHANGE:") || line.starts_with("BREAKING-CHANGE:")
+        });
+
+        let conventional = commit
+            .subject
+            .split_once(": ")
+            .and_then(|(prefix, description)| {
+                let (prefix, bang) = match prefix.strip_suffix('!') {
+                    Some(prefix) => (prefix, true),
+                    None => (prefix, false),
+                };
+
+                let (kind, scope) = match prefix.split_once('(') {
+                    Some((kind, scope)) => (kind, Some(scope.strip_suffix(')')?.to_string())),
+                    None => (prefix, None),
+                };
+
+                let is_word = !kind.is_empty() && kind.chars().all(|c| c.is_ascii_alphabetic());
+                is_word.then(|| (kind.to_lowercase(), scope, bang, description.to_string()))
+            });
+
+        let (kind, scope, bang, description) = conventional
+            .unwrap_or_else(|| ("other".to_string(), None, false, commit.subject.clone()));
+
+        Self {
+            hash: commit.hash.chars().take(7).collect(),
+            kind,
+            scope,
+            breaking: bang || breaking_footer,
+            description,
+        }
+    }
+
+    fn render(&self) -> String {
+        match &self.scope {
+            Some(scope) => format!("- **{scope}:** {} ({})", self.description, self.hash),
+            None => format!("- {} ({})", self.description, self.hash),
+        }
+    }
+}
+
+/// The pending changelog section for a crate's next release
+#[derive(Serialize, Clone, PartialEq, Debug)]
+pub struct Changelog {
+    /// The version of the last release tag, if the crate has been tagged before
+    pub baseline: Option<Version>,
+    pub version: Version,
+    pub date: String,
+    pub entries: Vec<ChangelogEntry>,
+}
+
+impl Changelog {
+    pub fn is_breaking(&self) -> bool {
+        self.entries.iter().any(|entry| entry.breaking)
+    }
+
+    pub fn has_features(&self) -> bool {
+        self.entries.iter().any(|entry| entry.kind == "feat")
+    }
+
+    /// The bump the commits call for, measured from the last release
+    pub fn required_bump(&self) -> Option<(&Version, BumpLevel)> {
+        let baseline = self.baseline.as_ref()?;
+        let level = BumpLevel::for_change(baseline, self.is_breaking(), self.has_features());
+        Some((baseline, level))
+    }
+
+    /// The markdown section, breaking changes first and then one list per commit type
+    pub fn render(&self) -> String {
+        let mut out = format!("## {} - {}\n", self.version, self.date);
+
+        let breaking = self
+            .entries
+            .iter()
+            .filter(|entry| entry.breaking)
+            .collect::<Vec<_>>();
+        if !breaking.is_empty() {
+            out.push_str("\n### ⚠ Breaking Changes\n\n");
+            for entry in breaking {
+                out.push_str(&entry.render());
+                out.push('\n');
+            }
+        }
+
+        for (kind, heading) in GROUPS {
+            let in_group = |entry: &&ChangelogEntry| match *kind {
+                "other" => is_other(&entry.kind),
+                kind => entry.kind == kind,
+            };
+            let entries = self.entries.iter().filter(in_group).collect::<Vec<_>>();
+
+            if entries.is_empty() {
+                continue;
+            }
+
+            out.push_str(&format!("\n### {heading}\n\n"));
+            for entry in entries {
+                out.push_str(&entry.render());
+                out.push('\n');
+            }
+        }
+
+        out
+    }
+}
+
+/// Types without a group of their own
+fn is_other(kind: &str) -> bool {
+    !GROUPS.iter().any(|(group, _)| *group == kind)
+}
+
+/// Gather the commits touching a crate since its last release tag into a section for the
+/// crate's current version
+pub async fn collect_changelog(graph: &CrateGraph, id: &PackageId) -> anyhow::Result<Changelog> {
+    let last_tag = graph.last_release_tag(id).await?;
+    let (dir, nested) = graph.crate_paths(id);
+    let since = last_tag.as_ref().map(|(tag, _)| tag.as_str());
+
+    let entries = git::log(&graph.meta.workspace_root, since, &dir, &nested)
+        .await?
+        .iter()
+        .map(ChangelogEntry::parse)
+        .filter(|entry| !SKIPPED.contains(&entry.kind.as_str()))
+        .collect();
+
+    Ok(Changelog {
+        baseline: last_tag.map(|(_, version)| version),
+        version: graph.get_crate(id).version.clone(),
+        date: today(),
+        entries,
+    })
+}
+
+pub fn changelog_path(graph: &CrateGraph, id: &PackageId) -> Utf8PathBuf {
+    let manifest_path = &graph.get_crate(id).manifest_path;
+    manifest_path.with_file_name(CHANGELOG_FILE)
+}
+
+/// Put a new section at the top of a changelog, below its title if it has one
+pub fn prepend_section(existing: &str, section: &str) -> String {
+    if existing.trim().is_empty() {
+        return format!("# Changelog\n\n{section}");
+    }
+
+    match existing.split_once('\n') {
+        Some((title, rest)) if title.starts_with("# ") => {
+            format!("{title}\n\n{section}\n{}", rest.trim_start_matches('\n'))
+        }
+        _ => format!("{section}\n{existing}"),
+    }
+}
+
+/// Where the section for `version` sits in a changelog, from its heading up to the next one
+pub fn find_section(contents: &str, version: &Version) -> Option<std::ops::Range<usize>> {
+    let version = version.to_string();
+    let mut offset = 0;
+    let mut start = None;
+
+    for line in contents.split_inclusive('\n') {
+        if let Some(heading) = line.strip_prefix("## ") {
+            if start.is_some() {
+                return start.map(|start| start..offset);
+            }
+
+            // Accept both `## 1.2.0 - date` and keep-a-changelog's `## [1.2.0] - date`
+            let heading = heading.trim_start_matches('[');
+            let matches = heading
+                .strip_prefix(version.as_str())
+                .map(|rest| rest.is_empty() || rest.starts_with([' ', ']', '\n', '\r']))
+                .unwrap_or(false);
+
+            if matches {
+                start = Some(offset);
+            }
+        }
+
+        offset += line.len();
+    }
+
+    start.map(|start| start..contents.len())
+}
+
+/// The changelog with the section for `version` swapped for `section`, or added at the top
+pub fn with_section(existing: &str, version: &Version, section: &str) -> String {
+    let section = format!("{}\n", section.trim_end());
+
+    match find_section(existing, version) {
+        Some(range) => {
+            let rest = &existing[range.end..];
+            let gap = if rest.is_empty() { "" } else { "\n" };
+            format!("{}{section}{gap}{rest}", &existing[..range.start])
+        }
+        None => prepend_section(existing, &section),
+    }
+}
+
+/// Whether the crate's CHANGELOG.md has a section for its current version
+pub fn has_entry(package: &Package) -> bool {
+    let path = package.manifest_path.with_file_name(CHANGELOG_FILE);
+
+    std::fs::read_to_string(path)
+        .map(|contents| find_section(&contents, &package.version).is_some())
+        .unwrap_or(false)
+}
+
+/// Write the pending section into the crate's CHANGELOG.md, replacing any section already there
+/// for the same version
+///
+/// The section is for the crate's current version, so that version mustn't be out yet: writing
+/// before a bump would put the unreleased commits over the released notes.
+pub async fn write_changelog(
+    graph: &CrateGraph,
+    id: &PackageId,
+    changelog: &Changelog,
+) -> anyhow::Result<()> {
+    let package = graph.get_crate(id);
+
+    if graph.is_released(id).await {
+        anyhow::bail!(
+            "{} {} is already released, bump it before writing its changelog",
+            package.name,
+            package.version
+        );
+    }
+
+    if changelog.entries.is_empty() {
+        anyhow::bail!("{} has no changes since its last release", package.name);
+    }
+
+    let path = changelog_path(graph, id);
+    let existing = std::fs::read_to_string(&path).unwrap_or_default();
+    let contents = with_section(&existing, &changelog.version, &changelog.render());
+    std::fs::write(&path, contents)?;
+    Ok(())
+}
+
+/// Today's date as YYYY-MM-DD, in UTC
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default();
+
+    // Days since the epoch to a civil date, from Howard Hinnant's `civil_from_days`
+    let days = (secs / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASED: &str = "# Changelog\n\n\
+        ## 0.2.0 - 2024-01-01\n\n- released notes (abc1234)\n\n\
+        ## [0.1.0] - 2023-06-01\n\n- first release (def5678)\n";
+
+    fn version(version: &str) -> Version {
+        version.parse().unwrap()
+    }
+
+    #[test]
+    fn finds_sections_by_exact_version() {
+        let range = find_section(RELEASED, &version("0.2.0")).unwrap();
+        assert!(RELEASED[range.clone()].starts_with("## 0.2.0"));
+        assert!(RELEASED[range].ends_with("(abc1234)\n\n"));
+
+        let range = find_section(RELEASED, &version("0.1.0")).unwrap();
+        assert!(RELEASED[range].starts_with("## [0.1.0]"));
+
+        assert_eq!(find_section(RELEASED, &version("0.2.0-alpha.1")), None);
+        assert_eq!(find_section(RELEASED, &version("0.3.0")), None);
+    }
+
+    #[test]
+    fn new_version_goes_above_released_notes() {
+        let section = "## 0.3.0 - 2024-02-01\n\n- unreleased (1111111)\n";
+        let written = with_section(RELEASED, &version("0.3.0"), section);
+
+        assert!(written.starts_with("# Changelog\n\n## 0.3.0 - 2024-02-01\n"));
+        assert!(written.ends_with(RELEASED.trim_start_matches("# Changelog\n\n")));
+    }
+
+    #[test]
+    fn rewriting_the_pending_version_leaves_released_notes_alone() {
+        let first = "## 0.3.0 - 2024-02-01\n\n- unreleased (1111111)\n";
+        let second = "## 0.3.0 - 2024-02-02\n\n- unreleased (1111111)\n- another (2222222)\n";
+
+        let written = with_section(RELEASED, &version("0.3.0"), first);
+        let rewritten = with_section(&written, &version("0.3.0"), second);
+
+        assert_eq!(rewritten, with_section(RELEASED, &version("0.3.0"), second));
+        let range = find_section(&rewritten, &version("0.2.0")).unwrap();
+        assert_eq!(
+            &rewritten[range],
+            "## 0.2.0 - 2024-01-01\n\n- released notes (abc1234)\n\n"
+        );
+    }
+}
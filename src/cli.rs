@@ -0,0 +1,517 @@
+//! Command line arguments, and the headless commands that run without opening a window
+use crate::changelog::{collect_changelog, write_changelog};
+use crate::rows::{release_blockers, run_release};
+use crate::state::{BumpLevel, CrateGraph, WorkspaceOptions};
+use cargo_metadata::PackageId;
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use std::{collections::HashSet, path::PathBuf, process::ExitCode};
+
+#[derive(Parser)]
+#[command(
+    name = "cargo-easy-release",
+    bin_name = "cargo easy-release",
+    about = "A visual tool for releasing workspaces of crates"
+)]
+pub struct Args {
+    /// The workspace to release. Defaults to the current directory
+    pub path: Option<PathBuf>,
+
+    /// Path to the workspace's Cargo.toml
+    #[arg(long, global = true, conflicts_with = "path")]
+    pub manifest_path: Option<PathBuf>,
+
+    /// Only release these crates
+    #[arg(short, long = "package", global = true, value_name = "SPEC")]
+    pub packages: Vec<String>,
+
+    /// Leave these crates out entirely
+    #[arg(long, global = true, value_name = "SPEC")]
+    pub exclude: Vec<String>,
+
+    /// Features to activate when resolving and publishing, space or comma separated
+    #[arg(short = 'F', long, global = true, value_delimiter = ',')]
+    pub features: Vec<String>,
+
+    /// Extra cargo config file passed through to cargo
+    #[arg(long, global = true, value_name = "FILE")]
+    pub config: Option<PathBuf>,
+
+    /// Registry to publish to, and look published versions up in, instead of crates.io
+    #[arg(long, global = true)]
+    pub registry: Option<String>,
+
+    /// Don't touch the network: no index lookups and cargo runs with `--offline`
+    #[arg(long, global = true)]
+    pub offline: bool,
+
+    /// Print results as JSON instead of text
+    #[arg(long, global = true)]
+    pub json: bool,
+
+    /// Run without the GUI
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Args {
+    /// Parse the process arguments, whether we were run directly or as `cargo easy-release`
+    ///
+    /// Cargo runs subcommands as `cargo-easy-release easy-release <args>`, so the extra
+    /// subcommand name needs to go before clap sees it.
+    pub fn from_env() -> Self {
+        let mut args = std::env::args_os().collect::<Vec<_>>();
+
+        if args
+            .get(1)
+            .map(|arg| arg == "easy-release")
+            .unwrap_or(false)
+        {
+            args.remove(1);
+        }
+
+        Self::parse_from(args)
+    }
+
+    pub fn options(&self) -> WorkspaceOptions {
+        let manifest_path = self
+            .manifest_path
+            .clone()
+            .or_else(|| self.path.as_ref().map(|path| path.join("Cargo.toml")));
+
+        WorkspaceOptions {
+            manifest_path,
+            packages: self.packages.clone(),
+            exclude: self.exclude.clone(),
+            features: self
+                .features
+                .iter()
+                .flat_map(|features| features.split_whitespace())
+                .map(ToString::to_string)
+                .collect(),
+            config: self.config.clone(),
+            registry: self.registry.clone(),
+            offline: self.offline,
+            resolve: false,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Check every crate can be published. Exits non-zero if any are blocked
+    Check,
+
+    /// Print the order crates would be released in
+    Plan {
+        /// Only plan these crates and the workspace dependencies they need released
+        crates: Vec<String>,
+    },
+
+    /// Bump a crate's version along with the requirement of every crate that depends on it
+    Bump {
+        #[arg(value_name = "CRATE")]
+        krate: String,
+
+        #[arg(value_enum)]
+        level: BumpLevel,
+
+        /// Pre-release label for the new version, like `alpha.1`
+        #[arg(long, value_name = "LABEL")]
+        pre: Option<String>,
+
+        /// Print the manifest edits without writing them
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Publish crates in dependency order, stopping at the first failure. Crates whose version is
+    /// already on the index are skipped
+    Publish {
+        /// Only publish these crates and the workspace dependencies they need released
+        crates: Vec<String>,
+
+        /// Pass `--dry-run` through to `cargo publish`. Defaults to `dry-run` in the workspace
+        /// config, which is on unless the config turns it off
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Really publish, even if the workspace config defaults to a dry run
+        #[arg(long, conflicts_with = "dry_run")]
+        no_dry_run: bool,
+
+        /// Pass `--allow-dirty` through to `cargo publish`. Defaults to `allow-dirty` in the
+        /// workspace config
+        #[arg(long)]
+        allow_dirty: bool,
+    },
+
+    /// Print the changelog section for a crate's current version, from the commits since its last tag
+    Changelog {
+        #[arg(value_name = "CRATE")]
+        krate: String,
+
+        /// Add the section to the crate's CHANGELOG.md. Refused once the version is released, so
+        /// bump first
+        #[arg(long)]
+        write: bool,
+    },
+
+    /// Push the release tags for the current versions to the configured remote
+    PushTags {
+        /// Push here instead of the remote in the workspace config
+        #[arg(long)]
+        remote: Option<String>,
+    },
+}
+
+#[derive(Serialize)]
+struct CrateReport {
+    name: String,
+    version: String,
+    blockers: Vec<String>,
+
+    /// Why `publish` leaves the crate out, like its version already being on the index
+    #[serde(skip_serializing_if = "Option::is_none")]
+    skipped: Option<String>,
+
+    /// Whether anything changed since the crate's last release tag, when `check` looked
+    #[serde(skip_serializing_if = "Option::is_none")]
+    needs_release: Option<bool>,
+}
+
+#[derive(Serialize)]
+struct EditReport {
+    manifest_path: String,
+    before: String,
+    after: String,
+}
+
+pub async fn run(graph: CrateGraph, command: Command, json: bool) -> ExitCode {
+    match execute(graph, command, json).await {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn execute(mut graph: CrateGraph, command: Command, json: bool) -> anyhow::Result<ExitCode> {
+    let ignored = graph.default_ignored();
+
+    match command {
+        Command::Check => {
+            let mut reports = vec![];
+
+            for id in graph
+                .release_order()
+                .iter()
+                .filter(|id| !ignored.contains(id))
+            {
+                let changes = graph.changes_since_release(id).await.ok().flatten();
+                reports.push(CrateReport {
+                    needs_release: changes.map(|changes| changes.needs_release()),
+                    ..report(&graph, id).await
+                });
+            }
+
+            let blocked = reports.iter().any(|report| !report.blockers.is_empty());
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&reports)?);
+            } else {
+                for report in &reports {
+                    let note = match report.needs_release {
+                        Some(false) => " (no release needed)",
+                        _ => "",
+                    };
+
+                    match report.blockers.is_empty() {
+                        true => println!("✅ {} {}{note}", report.name, report.version),
+                        false => println!(
+                            "❌ {} {}: {}",
+                            report.name,
+                            report.version,
+                            report.blockers.join(", ")
+                        ),
+                    }
+                }
+            }
+
+            Ok(exit_code(!blocked))
+        }
+
+        Command::Plan { crates } => {
+            let plan = plan(&graph, &crates, &ignored).await?;
+            let published = published(&graph, &plan).await;
+            print_plan(&graph, &plan, &published, json).await?;
+
+            Ok(ExitCode::SUCCESS)
+        }
+
+        Command::Bump {
+            krate,
+            level,
+            pre,
+            dry_run,
+        } => {
+            let id = find_crate(&graph, &krate)?;
+            let edits = graph.plan_bump(&id, level, pre.as_deref())?;
+
+            if !dry_run {
+                for edit in &edits {
+                    edit.apply()?;
+                }
+            }
+
+            if json {
+                let edits = edits
+                    .iter()
+                    .map(|edit| EditReport {
+                        manifest_path: edit.manifest_path.to_string(),
+                        before: edit.before.clone(),
+                        after: edit.after.clone(),
+                    })
+                    .collect::<Vec<_>>();
+
+                println!("{}", serde_json::to_string_pretty(&edits)?);
+            } else {
+                let version = graph.next_version(&id, level, pre.as_deref())?;
+                let names = graph
+                    .bump_targets(&id)
+                    .iter()
+                    .map(|id| graph.get_crate(id).name.as_str())
+                    .collect::<Vec<_>>();
+                println!("Bumping {} to {version}", names.join(", "));
+
+                for edit in &edits {
+                    println!("  {}", edit.manifest_path);
+                }
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+
+        Command::Publish {
+            crates,
+            dry_run,
+            no_dry_run,
+            allow_dirty,
+        } => {
+            // Same defaults as the window's checkboxes
+            let dry_run = dry_run || (graph.config.dry_run && !no_dry_run);
+            let allow_dirty = allow_dirty || graph.config.allow_dirty;
+
+            let plan = plan(&graph, &crates, &ignored).await?;
+            let published = published(&graph, &plan).await;
+            print_plan(&graph, &plan, &published, json).await?;
+
+            // Versions already on the index would only fail with "already exists"
+            let plan = plan
+                .into_iter()
+                .filter(|id| !published.contains(id))
+                .collect::<Vec<_>>();
+
+            // Refuse up front rather than leaving the registry with half a release
+            for id in &plan {
+                let report = report(&graph, id).await;
+
+                if !report.blockers.is_empty() {
+                    anyhow::bail!(
+                        "{} can't be published: {}",
+                        report.name,
+                        report.blockers.join(", ")
+                    );
+                }
+            }
+
+            if !allow_dirty {
+                for id in &plan {
+                    let status = graph.working_tree_status(id).await?;
+
+                    if !status.is_clean() {
+                        anyhow::bail!(
+                            "{} has uncommitted changes ({}), pass --allow-dirty to publish anyway",
+                            graph.get_crate(id).name,
+                            status.summary()
+                        );
+                    }
+                }
+            }
+
+            let mut released = vec![];
+            let mut result = Ok(());
+
+            for id in &plan {
+                let manifest_path = graph.get_crate(id).manifest_path.clone();
+                result =
+                    run_release(manifest_path, allow_dirty, dry_run, graph.options.clone()).await;
+
+                if result.is_err() {
+                    break;
+                }
+                released.push(id.clone());
+            }
+
+            // Whatever made it out gets recorded, even if the run stopped early
+            if !dry_run {
+                graph.record_release(&released).await?;
+            }
+
+            result?;
+            Ok(ExitCode::SUCCESS)
+        }
+
+        Command::Changelog { krate, write } => {
+            let id = find_crate(&graph, &krate)?;
+            let changelog = collect_changelog(&graph, &id).await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&changelog)?);
+            } else {
+                print!("{}", changelog.render());
+            }
+
+            if write {
+                write_changelog(&graph, &id, &changelog).await?;
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+
+        Command::PushTags { remote } => {
+            if let Some(remote) = remote {
+                graph.config.remote = remote;
+            }
+
+            let tags = graph.push_release_tags(&ignored).await?;
+
+            if json {
+                println!("{}", serde_json::to_string_pretty(&tags)?);
+            } else if tags.is_empty() {
+                println!("No release tags to push");
+            } else {
+                for tag in &tags {
+                    println!("Pushed {tag} to {}", graph.config.remote);
+                }
+            }
+
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+/// The crates to release in order: everything that isn't ignored, or the named crates plus the
+/// workspace dependencies they need. Ignored crates are left out and warned about
+async fn plan(
+    graph: &CrateGraph,
+    crates: &[String],
+    ignored: &HashSet<PackageId>,
+) -> anyhow::Result<Vec<PackageId>> {
+    if crates.is_empty() {
+        return Ok(graph
+            .release_order()
+            .into_iter()
+            .filter(|id| !ignored.contains(id))
+            .collect());
+    }
+
+    let selected = crates
+        .iter()
+        .map(|name| find_crate(graph, name))
+        .collect::<anyhow::Result<HashSet<_>>>()?;
+
+    let closure = graph.release_closure(&selected).await;
+    let closure = closure.difference(ignored).cloned().collect();
+    let plan = graph.subgraph(&closure).release_order();
+
+    for (id, dep) in graph.ignored_dependencies(&plan, ignored) {
+        eprintln!(
+            "warning: {} depends on {}, which is ignored",
+            graph.get_crate(&id).name,
+            graph.get_crate(&dep).name
+        );
+    }
+
+    Ok(plan)
+}
+
+/// The crates in the plan whose current version is already on the index
+async fn published(graph: &CrateGraph, plan: &[PackageId]) -> HashSet<PackageId> {
+    let mut published = HashSet::new();
+
+    for id in plan {
+        if graph.is_published(id).await {
+            published.insert(id.clone());
+        }
+    }
+
+    published
+}
+
+/// Print the plan, marking the crates that `publish` skips because they're already published
+async fn print_plan(
+    graph: &CrateGraph,
+    plan: &[PackageId],
+    published: &HashSet<PackageId>,
+    json: bool,
+) -> anyhow::Result<()> {
+    let skipped = |id: &PackageId| {
+        published
+            .contains(id)
+            .then(|| "already published".to_string())
+    };
+
+    if json {
+        let mut reports = vec![];
+        for id in plan {
+            reports.push(CrateReport {
+                skipped: skipped(id),
+                ..report(graph, id).await
+            });
+        }
+
+        println!("{}", serde_json::to_string_pretty(&reports)?);
+    } else {
+        for (idx, id) in plan.iter().enumerate() {
+            let package = graph.get_crate(id);
+            let note = match skipped(id) {
+                Some(reason) => format!(" (skipped: {reason})"),
+                None => String::new(),
+            };
+            println!("{}. {} {}{note}", idx + 1, package.name, package.version);
+        }
+    }
+
+    Ok(())
+}
+
+async fn report(graph: &CrateGraph, id: &PackageId) -> CrateReport {
+    let package = graph.get_crate(id);
+    let bumped = graph.has_unreleased_bump(id).await;
+
+    CrateReport {
+        name: package.name.clone(),
+        version: package.version.to_string(),
+        blockers: release_blockers(package, &graph.config, bumped),
+        skipped: None,
+        needs_release: None,
+    }
+}
+
+fn find_crate(graph: &CrateGraph, name: &str) -> anyhow::Result<PackageId> {
+    graph
+        .crates
+        .iter()
+        .find(|id| graph.get_crate(id).name == name)
+        .cloned()
+        .ok_or_else(|| anyhow::anyhow!("no crate named `{name}` in the workspace"))
+}
+
+fn exit_code(success: bool) -> ExitCode {
+    match success {
+        true => ExitCode::SUCCESS,
+        false => ExitCode::FAILURE,
+    }
+}
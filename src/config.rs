@@ -0,0 +1,294 @@
+//! Per-workspace settings, kept in `easy-release.toml` or `[workspace.metadata.easy-release]`
+use crate::cargo_util::matches_pattern;
+use crate::state::BumpLevel;
+use cargo_metadata::{camino::Utf8Path, semver::Version, Metadata};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use toml_edit::{Document, Item, Table, TableLike};
+
+pub const CONFIG_FILE: &str = "easy-release.toml";
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct WorkspaceConfig {
+    /// Default for the "Allow dirty?" checkbox and `publish --allow-dirty`
+    pub allow_dirty: bool,
+
+    /// Default for the "Dry run?" checkbox and `publish --dry-run`
+    pub dry_run: bool,
+
+    /// Crates to leave out of the release, on top of the ones with `publish = false`
+    pub ignored: BTreeSet<String>,
+
+    /// How release tags are named, with `{name}` and `{version}` filled in per crate
+    pub tag_pattern: String,
+
+    /// Commit the manifest changes once crates are published
+    pub commit: bool,
+
+    /// Message for those commits, with `{name}` and `{version}` filled in. A run that releases
+    /// several crates makes one commit, named for the first and listing the rest
+    pub commit_message: String,
+
+    /// Tag each published crate, named by `tag_pattern`
+    pub tag: bool,
+
+    /// Where "Push tags" sends the release tags
+    pub remote: String,
+
+    /// Block releasing a bumped crate whose CHANGELOG.md has no section for its new version
+    pub require_changelog: bool,
+
+    /// Version every crate together: bumping one bumps all the crates that aren't ignored
+    pub lockstep: bool,
+
+    /// Settings for individual crates, keyed by name
+    pub crates: BTreeMap<String, CrateConfig>,
+
+    /// Named sets of crates versioned under one policy, like `core-*` moving together
+    pub groups: BTreeMap<String, GroupConfig>,
+
+    /// Where the config was loaded from, and so where it gets written back to
+    #[serde(skip)]
+    pub in_manifest: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq, Debug)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct CrateConfig {
+    /// Set to false to never publish this crate, whatever its manifest says
+    pub publish: Option<bool>,
+
+    /// The smallest bump to suggest for this crate, for crates that always release as minor, say
+    pub bump: Option<BumpLevel>,
+
+    /// Release checks that don't apply to this crate, like `license` or `description`
+    pub skip_checks: BTreeSet<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct GroupConfig {
+    /// Names of the crates in the group, where `*` matches any run of characters
+    pub members: Vec<String>,
+
+    /// Bump every member together. Otherwise members keep their own versions but share the policy
+    pub lockstep: bool,
+
+    /// The smallest bump to suggest for members without a policy of their own
+    pub bump: Option<BumpLevel>,
+}
+
+impl Default for GroupConfig {
+    fn default() -> Self {
+        Self {
+            members: vec![],
+            lockstep: true,
+            bump: None,
+        }
+    }
+}
+
+impl Default for WorkspaceConfig {
+    fn default() -> Self {
+        Self {
+            allow_dirty: false,
+            dry_run: true,
+            ignored: BTreeSet::new(),
+            tag_pattern: "{name}-v{version}".to_string(),
+            commit: false,
+            commit_message: "chore: release {name} v{version}".to_string(),
+            tag: false,
+            remote: "origin".to_string(),
+            require_changelog: false,
+            lockstep: false,
+            crates: BTreeMap::new(),
+            groups: BTreeMap::new(),
+            in_manifest: false,
+        }
+    }
+}
+
+impl WorkspaceConfig {
+    /// Read the config file if there is one, falling back to the workspace metadata table
+    pub fn load(meta: &Metadata) -> anyhow::Result<Self> {
+        let path = meta.workspace_root.join(CONFIG_FILE);
+
+        if path.exists() {
+            return Ok(toml::from_str(&std::fs::read_to_string(&path)?)?);
+        }
+
+        match meta.workspace_metadata.get("easy-release") {
+            Some(table) => Ok(Self {
+                in_manifest: true,
+                ..serde_json::from_value(table.clone())?
+            }),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Write the config back to wherever it came from. New configs go in their own file
+    ///
+    /// The existing file is edited in place, so comments and formatting survive a save.
+    pub fn save(&self, workspace_root: &Utf8Path) -> anyhow::Result<()> {
+        let new = toml::to_string(self)?.parse::<Document>()?;
+        let defaults = toml::to_string(&Self::default())?.parse::<Document>()?;
+
+        let path = match self.in_manifest {
+            true => workspace_root.join("Cargo.toml"),
+            false => workspace_root.join(CONFIG_FILE),
+        };
+        let mut doc = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents.parse::<Document>()?,
+            Err(_) => Document::new(),
+        };
+
+        let table = match self.in_manifest {
+            true => {
+                let item = &mut doc["workspace"]["metadata"]["easy-release"];
+                if !item.is_table_like() {
+                    *item = Item::Table(Table::new());
+                }
+                item.as_table_like_mut().unwrap()
+            }
+            false => doc.as_table_mut() as &mut dyn TableLike,
+        };
+
+        merge_table(table, new.as_table(), Some(defaults.as_table()));
+        std::fs::write(&path, doc.to_string())?;
+
+        Ok(())
+    }
+
+    pub fn crate_config(&self, name: &str) -> Option<&CrateConfig> {
+        self.crates.get(name)
+    }
+
+    /// Whether the config leaves this crate out of the release
+    pub fn is_ignored(&self, name: &str) -> bool {
+        self.ignored.contains(name)
+            || self.crate_config(name).and_then(|krate| krate.publish) == Some(false)
+    }
+
+    pub fn skips_check(&self, name: &str, check: &str) -> bool {
+        self.crate_config(name)
+            .map(|krate| krate.skip_checks.contains(check))
+            .unwrap_or(false)
+    }
+
+    /// The release tag for this version of a crate
+    pub fn release_tag(&self, name: &str, version: &Version) -> String {
+        fill_template(&self.tag_pattern, name, version)
+    }
+
+    /// The message for the commit that records a release run: `commit_message` for a lone crate,
+    /// or for the first crate with the rest counted, and every crate listed below
+    pub fn release_commit_message(&self, released: &[(&str, &Version)]) -> String {
+        let Some(((name, version), rest)) = released.split_first() else {
+            return String::new();
+        };
+
+        let subject = fill_template(&self.commit_message, name, version);
+        if rest.is_empty() {
+            return subject;
+        }
+
+        let list = released
+            .iter()
+            .map(|(name, version)| format!("- {name} v{version}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!("{subject} and {} more\n\n{list}", rest.len())
+    }
+
+    /// The version a tag marks the release of, if it's one of this crate's release tags
+    pub fn parse_release_tag(&self, name: &str, tag: &str) -> Option<Version> {
+        let pattern = self.tag_pattern.replace("{name}", name);
+        let (prefix, suffix) = pattern.split_once("{version}")?;
+
+        tag.strip_prefix(prefix)?.strip_suffix(suffix)?.parse().ok()
+    }
+
+    /// The crate's own bump policy, or else its group's
+    pub fn bump_policy(&self, name: &str) -> Option<BumpLevel> {
+        self.crate_config(name)
+            .and_then(|krate| krate.bump)
+            .or_else(|| self.group_of(name).and_then(|(_, group)| group.bump))
+    }
+
+    /// The first group with a member pattern matching the crate
+    pub fn group_of(&self, name: &str) -> Option<(&str, &GroupConfig)> {
+        let is_member = |group: &GroupConfig| {
+            group
+                .members
+                .iter()
+                .any(|pattern| matches_pattern(pattern, name))
+        };
+
+        self.groups
+            .iter()
+            .find(|(_, group)| is_member(group))
+            .map(|(group_name, group)| (group_name.as_str(), group))
+    }
+}
+
+fn fill_template(template: &str, name: &str, version: &Version) -> String {
+    template
+        .replace("{name}", name)
+        .replace("{version}", &version.to_string())
+}
+
+/// Write `new` over `old` in place, keeping the comments, key order and formatting of everything
+/// that didn't change. Keys that `new` no longer has are removed, and keys `old` doesn't have yet
+/// are only added when they differ from `defaults`, so a save doesn't spell out every default
+fn merge_table(old: &mut dyn TableLike, new: &dyn TableLike, defaults: Option<&dyn TableLike>) {
+    let removed = old
+        .iter()
+        .map(|(key, _)| key.to_string())
+        .filter(|key| !new.contains_key(key))
+        .collect::<Vec<_>>();
+
+    for key in removed {
+        old.remove(&key);
+    }
+
+    for (key, item) in new.iter() {
+        match (old.get_mut(key), item) {
+            (Some(old_item), _) if old_item.is_table_like() && item.is_table_like() => merge_table(
+                old_item.as_table_like_mut().unwrap(),
+                item.as_table_like().unwrap(),
+                None,
+            ),
+            (Some(Item::Value(old_value)), Item::Value(value)) => {
+                if !same_item(&Item::Value(old_value.clone()), item) {
+                    let decor = old_value.decor().clone();
+                    *old_value = value.clone();
+                    *old_value.decor_mut() = decor;
+                }
+            }
+            (Some(old_item), _) => *old_item = item.clone(),
+            (None, _) => {
+                let is_default = defaults
+                    .and_then(|defaults| defaults.get(key))
+                    .map(|default| same_item(default, item))
+                    .unwrap_or(false);
+
+                if !is_default {
+                    old.insert(key, item.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Whether two items hold the same data, however they're formatted
+fn same_item(a: &Item, b: &Item) -> bool {
+    let parse = |item: &Item| {
+        let mut doc = Document::new();
+        doc["item"] = item.clone();
+        doc.to_string().parse::<toml::Value>().ok()
+    };
+
+    parse(a) == parse(b)
+}
@@ -1,21 +1,170 @@
+use crate::diff::DiffPreview;
+use crate::rows::{release_blockers, run_release};
 use crate::state::*;
+use crate::watch::reload_workspace;
+use cargo_metadata::PackageId;
 use dioxus::prelude::*;
 use fermi::use_atom_state;
+use std::collections::HashSet;
 
-pub fn GlobalConfig(cx: Scope) -> Element {
+#[inline_props]
+pub fn GlobalConfig<'a>(
+    cx: Scope<'a>,
+    graph: &'a UseState<CrateGraph>,
+    ignored_crates: &'a UseState<HashSet<PackageId>>,
+    selected: &'a UseState<HashSet<String>>,
+) -> Element {
     let allow_dirty = use_atom_state(cx, ALLOW_DIRTY);
     let dry_run = use_atom_state(cx, DRY_RUN);
+    let pre_release = use_atom_state(cx, PRE_RELEASE);
+
+    let num_crates = graph.crates.len() - ignored_crates.len();
+    let sync_preview = use_state(cx, || None::<Vec<ManifestEdit>>);
+    let selected_plan = use_state(cx, || None::<Vec<PackageId>>);
+
+    // Release crates in the given order, skipping the ones that can't be published
+    let publish = move |order: Vec<PackageId>| {
+        let allow_dirty = *allow_dirty.get();
+        let dry_run = *dry_run.get();
+        let graph = graph.get().clone();
+
+        for (id, dep) in graph.ignored_dependencies(&order, ignored_crates) {
+            let (package, dep) = (graph.get_crate(&id), graph.get_crate(&dep));
+            println!("Warning: {} depends on ignored {}", package.name, dep.name);
+        }
+
+        cx.spawn(async move {
+            let mut released = vec![];
+
+            for id in order {
+                let package = graph.get_crate(&id);
+
+                if graph.is_published(&id).await {
+                    println!(
+                        "Skipping {}: {} is already published",
+                        package.name, package.version
+                    );
+                    continue;
+                }
+
+                let bumped = graph.has_unreleased_bump(&id).await;
+                let blockers = release_blockers(package, &graph.config, bumped);
+
+                if !blockers.is_empty() {
+                    println!("Skipping {}: {}", package.name, blockers.join(", "));
+                    continue;
+                }
+
+                if !allow_dirty {
+                    match graph.working_tree_status(&id).await {
+                        Ok(status) if status.is_clean() => {}
+                        Ok(status) => {
+                            let summary = status.summary();
+                            println!("Skipping {}: uncommitted changes ({summary})", package.name);
+                            continue;
+                        }
+                        Err(err) => {
+                            println!("Couldn't read git status for {}: {err}", package.name)
+                        }
+                    }
+                }
+
+                let manifest_path = package.manifest_path.clone();
+                let options = graph.options.clone();
+                if let Err(err) = run_release(manifest_path, allow_dirty, dry_run, options).await {
+                    println!("{err}");
+                    break;
+                }
+
+                released.push(id);
+            }
+
+            // Whatever made it out gets recorded, even if the run stopped early
+            if !dry_run {
+                if let Err(err) = graph.record_release(&released).await {
+                    println!("Failed to commit and tag the release: {err}");
+                }
+            }
+        });
+    };
+
+    let push_tags = move |_| {
+        let graph = graph.get().clone();
+        let ignored = ignored_crates.get().clone();
+
+        cx.spawn(async move {
+            match graph.push_release_tags(&ignored).await {
+                Ok(tags) if tags.is_empty() => println!("No release tags to push"),
+                Ok(tags) => println!("Pushed {} to {}", tags.join(", "), graph.config.remote),
+                Err(err) => println!("Failed to push tags: {err}"),
+            }
+        });
+    };
+
+    let release_all = move |_| {
+        publish(
+            graph
+                .release_order()
+                .into_iter()
+                .filter(|id| !ignored_crates.contains(id))
+                .collect(),
+        )
+    };
+
+    // Work out what else has to ship with the selected crates before showing the plan
+    let plan_selected = move |_| {
+        let graph = graph.get().clone();
+        let ignored = ignored_crates.get().clone();
+        let selected_plan = selected_plan.clone();
+        let selected_ids = graph
+            .crates
+            .iter()
+            .filter(|id| selected.contains(&graph.get_crate(id).name))
+            .cloned()
+            .collect::<HashSet<_>>();
+
+        cx.spawn(async move {
+            let closure = graph.release_closure(&selected_ids).await;
+            let closure = closure.difference(&ignored).cloned().collect();
+            selected_plan.set(Some(graph.subgraph(&closure).release_order()));
+        });
+    };
+
+    let plan_summary = selected_plan.get().as_ref().map(|plan| {
+        let names = plan
+            .iter()
+            .map(|id| graph.get_crate(id).name.as_str())
+            .collect::<Vec<_>>();
+        let added = names
+            .iter()
+            .filter(|name| !selected.contains(**name))
+            .count();
+        let ignored_deps = graph
+            .ignored_dependencies(plan, ignored_crates)
+            .iter()
+            .map(|(id, dep)| {
+                let (package, dep) = (graph.get_crate(id), graph.get_crate(dep));
+                format!("{} depends on ignored {}", package.name, dep.name)
+            })
+            .collect::<Vec<_>>();
+
+        (names.join(" → "), added, ignored_deps)
+    });
 
     render! {
         div { class: "border-b border-gray-200 mb-4 pb-2 flex flex-row justify-between",
-            h3 { class: "text-md leading-tight font-medium text-gray-900 font-bold", "Workspace Crates (12)" }
+            h3 { class: "text-md leading-tight font-medium text-gray-900 font-bold", "Workspace Crates ({num_crates})" }
             div { class: "flex flex-row",
                 form {
                     input {
                         name: "allow-dirty",
                         r#type: "checkbox",
                         checked: "{allow_dirty}",
-                        onchange: move |_| allow_dirty.set(!allow_dirty.get())
+                        onchange: move |_| {
+                            let value = !allow_dirty.get();
+                            allow_dirty.set(value);
+                            update_config(graph, |config| config.allow_dirty = value);
+                        }
                     }
                     label { r#for: "allow-dirty", "Allow dirty?" }
                 }
@@ -23,11 +172,143 @@ pub fn GlobalConfig(cx: Scope) -> Element {
                     input {
                         r#type: "checkbox",
                         checked: "{dry_run}",
-                        onchange: move |_| dry_run.set(!dry_run.get())
+                        onchange: move |_| {
+                            let value = !dry_run.get();
+                            dry_run.set(value);
+                            update_config(graph, |config| config.dry_run = value);
+                        }
                     }
                     label { r#for: "dry-run", "Dry run?" }
                 }
+                form { class: "ml-4",
+                    input {
+                        r#type: "checkbox",
+                        checked: "{graph.config.lockstep}",
+                        onchange: move |_| {
+                            let value = !graph.config.lockstep;
+                            update_config(graph, |config| config.lockstep = value);
+                        }
+                    }
+                    label { r#for: "lockstep", "Lockstep?" }
+                }
+                input {
+                    class: "ml-4 w-28 border border-gray-200 rounded-md px-2",
+                    placeholder: "pre-release",
+                    value: "{pre_release}",
+                    oninput: move |evt| pre_release.set(evt.value.clone())
+                }
+                button {
+                    class: "ml-4 inline-flex items-center font-medium leading-6 text-gray-700 hover:text-gray-900 transition duration-200",
+                    onclick: move |_| match graph.sync_requirements() {
+                        Ok(edits) => sync_preview.set(Some(edits)),
+                        Err(err) => println!("Failed to sync requirements: {err}"),
+                    },
+                    "Sync requirements"
+                }
+                button {
+                    class: "ml-4 inline-flex items-center font-medium leading-6 text-gray-700 hover:text-gray-900 transition duration-200",
+                    onclick: push_tags,
+                    "Push tags"
+                }
+                if !selected.is_empty() {
+                    rsx! {
+                        button {
+                            class: "ml-4 inline-flex items-center font-medium leading-6 text-green-500 hover:text-green-600 transition duration-200",
+                            onclick: plan_selected,
+                            "Release selected ({selected.len()})"
+                        }
+                    }
+                }
+                button {
+                    class: "ml-4 inline-flex items-center font-medium leading-6 text-green-500 hover:text-green-600 transition duration-200",
+                    onclick: release_all,
+                    "Release all"
+                }
+            }
+        }
+
+        if let Some((order, added, ignored_deps)) = &plan_summary {
+            rsx! {
+                div { class: "mb-4 p-2 bg-gray-50 rounded-md shadow-md text-sm flex flex-row justify-between",
+                    div {
+                        div { class: "font-semibold text-gray-800", "Release plan" }
+                        div { class: "text-gray-600", "{order}" }
+                        div { class: "text-xs text-gray-500", "{added} workspace dependencies added because their published versions don't satisfy the requirements" }
+                        ignored_deps.iter().map(|warning| rsx! {
+                            div { class: "text-xs text-yellow-600", "⚠️ {warning}" }
+                        })
+                    }
+                    div {
+                        button {
+                            class: "mr-4 font-medium text-green-500 hover:text-green-600",
+                            onclick: move |_| {
+                                if let Some(plan) = selected_plan.get() {
+                                    publish(plan.clone());
+                                }
+                                selected_plan.set(None);
+                            },
+                            "Publish"
+                        }
+                        button { class: "font-medium text-gray-500 hover:text-gray-700", onclick: move |_| selected_plan.set(None), "Cancel" }
+                    }
+                }
+            }
+        }
+
+        if let Some(edits) = sync_preview.get() {
+            rsx! {
+                SyncPreview { graph: graph, ignored_crates: ignored_crates, edits: edits, preview: sync_preview }
             }
         }
     }
 }
+
+#[inline_props]
+fn SyncPreview<'a>(
+    cx: Scope<'a>,
+    graph: &'a UseState<CrateGraph>,
+    ignored_crates: &'a UseState<HashSet<PackageId>>,
+    edits: &'a Vec<ManifestEdit>,
+    preview: &'a UseState<Option<Vec<ManifestEdit>>>,
+) -> Element {
+    let apply = move |_| {
+        for edit in edits.iter() {
+            if let Err(err) = edit.apply() {
+                println!("Failed to write {}: {err}", edit.manifest_path);
+            }
+        }
+
+        let (graph, ignored_crates) = (graph.clone(), ignored_crates.clone());
+        cx.spawn(async move { reload_workspace(&graph, &ignored_crates).await });
+
+        preview.set(None);
+    };
+
+    let title = match edits.is_empty() {
+        true => "All workspace requirements are up to date",
+        false => "Requirements to sync",
+    };
+
+    render! {
+        div { class: "mb-4 p-2 bg-gray-50 rounded-md shadow-md",
+            div { class: "flex flex-row justify-between mb-2",
+                h3 { class: "text-md font-semibold text-gray-800", "{title}" }
+                div {
+                    if !edits.is_empty() {
+                        rsx! {
+                            button { class: "mr-4 font-medium text-green-500 hover:text-green-600", onclick: apply, "Apply" }
+                        }
+                    }
+                    button { class: "font-medium text-gray-500 hover:text-gray-700", onclick: move |_| preview.set(None), "Close" }
+                }
+            }
+
+            edits.iter().map(|edit| rsx! {
+                div { class: "mb-2",
+                    div { class: "text-xs text-gray-500 mb-1", "{edit.manifest_path}" }
+                    DiffPreview { before: &edit.before, after: &edit.after }
+                }
+            })
+        }
+    }
+}
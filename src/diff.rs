@@ -0,0 +1,80 @@
+//! Line diffs for previewing edits before they're written to disk
+use dioxus::prelude::*;
+
+/// How many unchanged lines to keep around each change in the preview
+const CONTEXT: usize = 2;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Diff two texts line by line using their longest common subsequence
+pub fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<DiffLine<'a>> {
+    let old = before.lines().collect::<Vec<_>>();
+    let new = after.lines().collect::<Vec<_>>();
+
+    // lcs[i][j] is the length of the longest common subsequence of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = match old[i] == new[j] {
+                true => lcs[i + 1][j + 1] + 1,
+                false => lcs[i + 1][j].max(lcs[i][j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut out = vec![];
+
+    while i < old.len() && j < new.len() {
+        if old[i] == new[j] {
+            out.push(DiffLine::Same(old[i]));
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            out.push(DiffLine::Removed(old[i]));
+            i += 1;
+        } else {
+            out.push(DiffLine::Added(new[j]));
+            j += 1;
+        }
+    }
+
+    out.extend(old[i..].iter().map(|line| DiffLine::Removed(line)));
+    out.extend(new[j..].iter().map(|line| DiffLine::Added(line)));
+
+    out
+}
+
+/// Render the changed lines of an edit, with a little context around each change
+#[inline_props]
+pub fn DiffPreview<'a>(cx: Scope<'a>, before: &'a str, after: &'a str) -> Element {
+    let lines = diff_lines(before, after);
+
+    let changed = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, DiffLine::Same(_)))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+
+    let near_change = |idx: usize| {
+        changed
+            .iter()
+            .any(|&change| idx + CONTEXT >= change && idx <= change + CONTEXT)
+    };
+
+    render! {
+        pre { class: "text-xs bg-gray-100 rounded-md p-2 overflow-x-auto",
+            lines.iter().enumerate().filter(|(idx, _)| near_change(*idx)).map(|(_, line)| match line {
+                DiffLine::Same(text) => rsx! { div { class: "text-gray-500", "  {text}" } },
+                DiffLine::Removed(text) => rsx! { div { class: "text-red-600 bg-red-50", "- {text}" } },
+                DiffLine::Added(text) => rsx! { div { class: "text-green-600 bg-green-50", "+ {text}" } },
+            })
+        }
+    }
+}
@@ -0,0 +1,373 @@
+//! Thin wrappers around the `git` command line
+use anyhow::bail;
+use cargo_metadata::camino::{Utf8Path, Utf8PathBuf};
+use tokio::process::Command;
+
+/// Run git in `dir` and return its trimmed stdout
+pub async fn git(dir: &Utf8Path, args: &[&str]) -> anyhow::Result<String> {
+    Ok(git_raw(dir, args).await?.trim().to_string())
+}
+
+/// Run git in `dir` and return its stdout untouched, for output where leading whitespace matters
+async fn git_raw(dir: &Utf8Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .await?;
+
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// The root of the git repository containing `dir`
+pub async fn toplevel(dir: &Utf8Path) -> anyhow::Result<Utf8PathBuf> {
+    Ok(git(dir, &["rev-parse", "--show-toplevel"]).await?.into())
+}
+
+/// Uncommitted changes under a directory, split the way `git status` shows them
+#[derive(Clone, Default, PartialEq, Debug)]
+pub struct WorkingTreeStatus {
+    pub staged: Vec<String>,
+    pub modified: Vec<String>,
+    pub untracked: Vec<String>,
+}
+
+impl WorkingTreeStatus {
+    pub fn is_clean(&self) -> bool {
+        self.staged.is_empty() && self.modified.is_empty() && self.untracked.is_empty()
+    }
+
+    /// Something like "2 staged, 1 modified", or "clean"
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.staged.len(), "staged"),
+            (self.modified.len(), "modified"),
+            (self.untracked.len(), "untracked"),
+        ];
+
+        let parts = counts
+            .iter()
+            .filter(|(count, _)| *count > 0)
+            .map(|(count, kind)| format!("{count} {kind}"))
+            .collect::<Vec<_>>();
+
+        match parts.is_empty() {
+            true => "clean".to_string(),
+            false => parts.join(", "),
+        }
+    }
+}
+
+/// The working-tree status of `path` (relative to `repo`), leaving out the `exclude`d subdirectories
+pub async fn status(
+    repo: &Utf8Path,
+    path: &Utf8Path,
+    exclude: &[Utf8PathBuf],
+) -> anyhow::Result<WorkingTreeStatus> {
+    let pathspecs = pathspecs(path, exclude);
+    let mut args = vec![
+        "status",
+        "--porcelain=v1",
+        "-z",
+        "--untracked-files=all",
+        "--",
+    ];
+    args.extend(pathspecs.iter().map(String::as_str));
+
+    Ok(parse_status(&git_raw(repo, &args).await?))
+}
+
+/// Read `git status --porcelain=v1 -z`, where paths are never quoted and each entry ends in a NUL
+///
+/// Renames and copies are followed by one more NUL-terminated field with the original path, which
+/// isn't part of the working tree any more so is skipped.
+fn parse_status(output: &str) -> WorkingTreeStatus {
+    let mut status = WorkingTreeStatus::default();
+    let mut entries = output.split('\0').filter(|entry| !entry.is_empty());
+
+    while let Some(entry) = entries.next() {
+        let (Some(code), Some(file)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        let file = file.to_string();
+
+        if code.starts_with(['R', 'C']) {
+            entries.next();
+        }
+
+        match code.as_bytes() {
+            [b'?', b'?'] => status.untracked.push(file),
+            [index, tree] => {
+                if *index != b' ' {
+                    status.staged.push(file.clone());
+                }
+                if *tree != b' ' {
+                    status.modified.push(file);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    status
+}
+
+/// All the tags in the repository
+pub async fn tags(repo: &Utf8Path) -> anyhow::Result<Vec<String>> {
+    Ok(git(repo, &["tag", "--list"])
+        .await?
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// How many commits since `rev` touched `path`, leaving out the `exclude`d subdirectories
+pub async fn commit_count(
+    repo: &Utf8Path,
+    rev: &str,
+    path: &Utf8Path,
+    exclude: &[Utf8PathBuf],
+) -> anyhow::Result<usize> {
+    let range = format!("{rev}..HEAD");
+    let pathspecs = pathspecs(path, exclude);
+    let mut args = vec!["rev-list", "--count", &range, "--"];
+    args.extend(pathspecs.iter().map(String::as_str));
+
+    Ok(git(repo, &args).await?.parse()?)
+}
+
+/// One commit from `git log`
+#[derive(Clone, PartialEq, Debug)]
+pub struct Commit {
+    pub hash: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Commits that touched `path` since `since`, or in all of history, newest first
+pub async fn log(
+    repo: &Utf8Path,
+    since: Option<&str>,
+    path: &Utf8Path,
+    exclude: &[Utf8PathBuf],
+) -> anyhow::Result<Vec<Commit>> {
+    let range = match since {
+        Some(rev) => format!("{rev}..HEAD"),
+        None => "HEAD".to_string(),
+    };
+    let pathspecs = pathspecs(path, exclude);
+
+    // Unit and record separators keep multi-line bodies in one piece
+    let mut args = vec!["log", "--format=%H%x1f%s%x1f%b%x1e", &range, "--"];
+    args.extend(pathspecs.iter().map(String::as_str));
+
+    Ok(git(repo, &args)
+        .await?
+        .split('\x1e')
+        .filter_map(|record| {
+            let mut fields = record.trim().splitn(3, '\x1f');
+            Some(Commit {
+                hash: fields.next().filter(|hash| !hash.is_empty())?.to_string(),
+                subject: fields.next()?.to_string(),
+                body: fields.next().unwrap_or_default().trim().to_string(),
+            })
+        })
+        .collect())
+}
+
+/// Commit whatever changed in the files matching `pathspecs`, leaving anything else that's staged
+/// alone. Returns false if there was nothing to commit
+pub async fn commit(repo: &Utf8Path, message: &str, pathspecs: &[&str]) -> anyhow::Result<bool> {
+    // Git refuses pathspecs that match nothing, like a lockfile that isn't checked in
+    let mut tracked = vec![];
+    for pathspec in pathspecs {
+        if !git(repo, &["ls-files", "--", pathspec]).await?.is_empty() {
+            tracked.push(*pathspec);
+        }
+    }
+
+    if tracked.is_empty() {
+        return Ok(false);
+    }
+
+    let mut add = vec!["add", "--update", "--"];
+    add.extend(&tracked);
+    git(repo, &add).await?;
+
+    // `diff --quiet` fails exactly when there's something staged
+    let mut diff = vec!["diff", "--cached", "--quiet", "--"];
+    diff.extend(&tracked);
+    if git(repo, &diff).await.is_ok() {
+        return Ok(false);
+    }
+
+    let mut commit = vec!["commit", "--message", message, "--"];
+    commit.extend(&tracked);
+    git(repo, &commit).await?;
+
+    Ok(true)
+}
+
+/// Create an annotated tag at HEAD. Returns false if the tag is already there, and fails if a tag
+/// by that name points at some other commit
+pub async fn tag(repo: &Utf8Path, name: &str, message: &str) -> anyhow::Result<bool> {
+    let target = format!("refs/tags/{name}^{{commit}}");
+
+    // `rev-parse --verify` fails when there's no such tag yet
+    if let Ok(existing) = git(repo, &["rev-parse", "--verify", "--quiet", &target]).await {
+        let head = git(repo, &["rev-parse", "HEAD"]).await?;
+
+        if existing != head {
+            bail!("tag {name} already exists at {existing}, not at HEAD");
+        }
+
+        return Ok(false);
+    }
+
+    git(repo, &["tag", "--annotate", name, "--message", message]).await?;
+    Ok(true)
+}
+
+/// Push the given tags to `remote`
+pub async fn push_tags(repo: &Utf8Path, remote: &str, tags: &[String]) -> anyhow::Result<()> {
+    let refs = tags
+        .iter()
+        .map(|tag| format!("refs/tags/{tag}"))
+        .collect::<Vec<_>>();
+
+    let mut args = vec!["push", remote];
+    args.extend(refs.iter().map(String::as_str));
+    git(repo, &args).await?;
+
+    Ok(())
+}
+
+/// Pathspecs matching `path` but none of the `exclude`d directories inside it
+fn pathspecs(path: &Utf8Path, exclude: &[Utf8PathBuf]) -> Vec<String> {
+    let path = match path.as_str() {
+        "" => ".".to_string(),
+        path => path.to_string(),
+    };
+
+    std::iter::once(path)
+        .chain(exclude.iter().map(|dir| format!(":(exclude){dir}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::RELEASE_PATHSPECS;
+
+    /// A fresh directory under the system temp dir, unique to this test run
+    fn scratch_dir(name: &str) -> Utf8PathBuf {
+        let dir = std::env::temp_dir().join(format!("easy-release-{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Utf8PathBuf::from_path_buf(dir).unwrap()
+    }
+
+    fn write(dir: &Utf8Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    #[test]
+    fn parses_renames_and_unquoted_paths() {
+        let output = "R  new.rs\0old.rs\0 M src/with space.rs\0?? caf\u{e9}.rs\0";
+        let status = parse_status(output);
+
+        assert_eq!(status.staged, ["new.rs"]);
+        assert_eq!(status.modified, ["src/with space.rs"]);
+        assert_eq!(status.untracked, ["caf\u{e9}.rs"]);
+    }
+
+    #[tokio::test]
+    async fn commits_tags_and_pushes_a_release() {
+        let root = scratch_dir("git");
+        let (work, remote) = (root.join("work"), root.join("remote.git"));
+
+        git(&root, &["init", "--quiet", "--bare", remote.as_str()])
+            .await
+            .unwrap();
+        git(&root, &["init", "--quiet", work.as_str()])
+            .await
+            .unwrap();
+        for (key, value) in [
+            ("user.name", "Release Test"),
+            ("user.email", "release@example.com"),
+            ("commit.gpgsign", "false"),
+            ("tag.gpgsign", "false"),
+        ] {
+            git(&work, &["config", key, value]).await.unwrap();
+        }
+        git(&work, &["remote", "add", "origin", remote.as_str()])
+            .await
+            .unwrap();
+
+        write(&work, "Cargo.toml", "[workspace]\n");
+        write(
+            &work,
+            "crates/a/Cargo.toml",
+            "[package]\nversion = \"0.1.0\"\n",
+        );
+        write(&work, "crates/a/src/lib.rs", "");
+        git(&work, &["add", "--all"]).await.unwrap();
+        git(&work, &["commit", "--quiet", "--message", "initial"])
+            .await
+            .unwrap();
+
+        // Only the manifest belongs in the release commit, the source change stays behind
+        write(
+            &work,
+            "crates/a/Cargo.toml",
+            "[package]\nversion = \"0.2.0\"\n",
+        );
+        write(&work, "crates/a/src/lib.rs", "pub fn new() {}\n");
+        assert!(commit(&work, "release a v0.2.0", RELEASE_PATHSPECS)
+            .await
+            .unwrap());
+
+        let committed = git(&work, &["show", "--name-only", "--format=", "HEAD"])
+            .await
+            .unwrap();
+        assert_eq!(committed, "crates/a/Cargo.toml");
+        let status = status(&work, Utf8Path::new(""), &[]).await.unwrap();
+        assert_eq!(status.modified, ["crates/a/src/lib.rs"]);
+        assert!(!commit(&work, "nothing", RELEASE_PATHSPECS).await.unwrap());
+
+        assert!(tag(&work, "a-v0.2.0", "a v0.2.0").await.unwrap());
+        assert!(!tag(&work, "a-v0.2.0", "a v0.2.0").await.unwrap());
+        let kind = git(&work, &["cat-file", "-t", "a-v0.2.0"]).await.unwrap();
+        assert_eq!(kind, "tag");
+
+        // A local tag that isn't asked for stays off the remote
+        tag(&work, "unrelated", "unrelated").await.unwrap();
+        push_tags(&work, "origin", &["a-v0.2.0".to_string()])
+            .await
+            .unwrap();
+
+        let refs = [
+            "for-each-ref",
+            "--format=%(refname) %(objectname)",
+            "refs/tags",
+        ];
+        let pushed = git(&remote, &refs).await.unwrap();
+        let local = git(&work, &["rev-parse", "refs/tags/a-v0.2.0"])
+            .await
+            .unwrap();
+        assert_eq!(pushed, format!("refs/tags/a-v0.2.0 {local}"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
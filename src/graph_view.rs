@@ -0,0 +1,164 @@
+use crate::state::{write_crate_dot, write_crate_graph, CrateGraph, Focus, ReleaseStatus};
+use cargo_metadata::{semver::Version, PackageId};
+use dioxus::prelude::*;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+};
+
+#[inline_props]
+pub fn GraphView<'a>(
+    cx: Scope<'a>,
+    graph: &'a UseState<CrateGraph>,
+    ignored_crates: &'a UseState<HashSet<PackageId>>,
+) -> Element {
+    let export_path = use_state(cx, || {
+        graph.meta.workspace_root.join("graph.svg").to_string()
+    });
+    let export_status = use_state(cx, || None::<String>);
+
+    // Focus by name rather than id, since the id changes whenever the crate is bumped
+    let focus_name = use_state(cx, || None::<String>);
+    let focus_dir = use_state(cx, || Focus::Both);
+    let focused_graph = move || {
+        let name = focus_name.get().as_ref()?;
+        let id = graph
+            .crates
+            .iter()
+            .find(|id| graph.get_crate(id).name == *name)?;
+        Some(graph.focus(id, **focus_dir))
+    };
+
+    let focused = focused_graph();
+    let shown = focused.as_ref().unwrap_or(graph);
+
+    let mut names = graph
+        .crates
+        .iter()
+        .map(|id| graph.get_crate(id).name.clone())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    // Ask the index which of the current versions are already out, and git which crates have
+    // changed since their last tag, again whenever a version changes
+    let versions = graph
+        .crates
+        .iter()
+        .map(|id| {
+            let package = graph.get_crate(id);
+            (id.clone(), package.name.clone(), package.version.clone())
+        })
+        .collect::<Vec<(PackageId, String, Version)>>();
+
+    let changes = use_future(cx, (versions.clone(),), |_| {
+        let graph = graph.get().clone();
+        async move { graph.release_changes().await }
+    });
+
+    let bumped = use_future(cx, (versions.clone(),), |_| {
+        let graph = graph.get().clone();
+        async move { graph.bumped_crates().await }
+    });
+
+    let published = use_future(cx, (versions,), |_| {
+        let graph = graph.get().clone();
+        async move { graph.published_crates().await }
+    });
+
+    let statuses = move || {
+        let none = HashSet::new();
+        let published = published.value().unwrap_or(&none);
+        let no_changes = HashMap::new();
+        let changes = changes.value().unwrap_or(&no_changes);
+        let bumped = bumped.value().unwrap_or(&none);
+        graph.release_statuses(ignored_crates, published, changes, bumped)
+    };
+    let svg = write_crate_graph(shown, &statuses());
+
+    let release_order = shown
+        .release_order()
+        .into_iter()
+        .filter(|id| !ignored_crates.contains(id))
+        .map(|id| graph.get_crate(&id).name.clone())
+        .collect::<Vec<_>>()
+        .join(" → ");
+
+    // The path box picks the location, the button picks the format
+    let export = move |extension: &str, contents: String| {
+        let path = PathBuf::from(export_path.get()).with_extension(extension);
+
+        export_status.set(Some(match std::fs::write(&path, contents) {
+            Ok(()) => format!("Exported to {}", path.display()),
+            Err(err) => format!("Failed to write {}: {err}", path.display()),
+        }));
+    };
+
+    render! {
+        div { class: "flex flex-row items-center mb-4 text-sm",
+            select {
+                class: "border border-gray-200 rounded-md px-2 py-1 mr-4",
+                onchange: move |evt| match evt.value.is_empty() {
+                    true => focus_name.set(None),
+                    false => focus_name.set(Some(evt.value.clone())),
+                },
+                option { value: "", "Whole workspace" }
+                names.iter().map(|name| rsx! {
+                    option { value: "{name}", selected: focus_name.get().as_ref() == Some(name), "{name}" }
+                })
+            }
+            select {
+                class: "border border-gray-200 rounded-md px-2 py-1 mr-4",
+                disabled: focus_name.is_none(),
+                onchange: move |evt| focus_dir.set(match evt.value.as_str() {
+                    "deps" => Focus::Dependencies,
+                    "dependents" => Focus::Dependents,
+                    _ => Focus::Both,
+                }),
+                option { value: "both", "Dependencies and dependents" }
+                option { value: "deps", "Dependencies" }
+                option { value: "dependents", "Dependents" }
+            }
+            input {
+                class: "flex-grow border border-gray-200 rounded-md px-2 py-1 font-mono",
+                value: "{export_path}",
+                oninput: move |evt| export_path.set(evt.value.clone())
+            }
+            button {
+                class: "ml-4 font-medium text-gray-700 hover:text-gray-900",
+                onclick: move |_| {
+                    let focused = focused_graph();
+                    export("svg", write_crate_graph(focused.as_ref().unwrap_or(graph), &statuses()))
+                },
+                "Export SVG"
+            }
+            button {
+                class: "ml-4 font-medium text-gray-700 hover:text-gray-900",
+                onclick: move |_| {
+                    let focused = focused_graph();
+                    export("dot", write_crate_dot(focused.as_ref().unwrap_or(graph), &statuses()))
+                },
+                "Export DOT"
+            }
+        }
+
+        if let Some(status) = export_status.get() {
+            rsx! { div { class: "text-xs text-gray-500 mb-2", "{status}" } }
+        }
+
+        div { class: "flex flex-row text-xs text-gray-600 mb-4",
+            ReleaseStatus::ALL.iter().map(|status| rsx! {
+                div { class: "flex flex-row items-center mr-4",
+                    span { class: "inline-block w-3 h-3 mr-1 border border-gray-400", style: "background-color: {status.color()}" }
+                    "{status.label()}"
+                }
+            })
+            if published.value().is_none() {
+                rsx! { span { class: "text-gray-400", "checking crates.io..." } }
+            }
+        }
+
+        div { class: "text-xs text-gray-600 mb-4", "Release order: {release_order}" }
+
+        div { class: "overflow-auto w-full", dangerous_inner_html: "{svg}" }
+    }
+}
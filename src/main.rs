@@ -1,23 +1,61 @@
 #![allow(non_snake_case)]
-use cargo_metadata::{Metadata, PackageId};
+// Without the window the components are still built, just never mounted
+#![cfg_attr(not(feature = "gui"), allow(dead_code))]
+use cargo_metadata::PackageId;
 use dioxus::prelude::*;
-use dioxus_desktop::{Config, LogicalSize, WindowBuilder};
 use dioxus_signals::use_init_signal_rt;
-use fermi::use_init_atom_root;
-use state::CrateGraph;
-use std::collections::HashSet;
+use fermi::{use_init_atom_root, use_set};
+use state::{CrateGraph, WorkspaceOptions, ALLOW_DIRTY, DRY_RUN, WORKING_TREE};
+use std::{
+    collections::{BTreeMap, HashSet},
+    process::ExitCode,
+};
 
+mod api_diff;
 mod cargo_util;
+mod changelog;
+mod cli;
+mod config;
 mod config_banner;
+mod diff;
+mod git;
+mod graph_view;
 mod header;
+mod manifest_diff;
+mod registry;
 mod rows;
 mod state;
 mod templates;
+mod watch;
+
+fn main() -> ExitCode {
+    let args = cli::Args::from_env();
+    let options = args.options();
+    registry::set_offline(args.offline);
+
+    if let Some(command) = args.command {
+        let graph = match CrateGraph::load(options) {
+            Ok(graph) => graph,
+            Err(err) => {
+                eprintln!("error: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+
+        let runtime = tokio::runtime::Runtime::new().expect("failed to start the tokio runtime");
+        return runtime.block_on(cli::run(graph, command, args.json));
+    }
+
+    launch_window(options)
+}
+
+#[cfg(feature = "gui")]
+fn launch_window(options: WorkspaceOptions) -> ExitCode {
+    use dioxus_desktop::{Config, LogicalSize, WindowBuilder};
 
-fn main() {
     dioxus_desktop::launch_with_props(
         app,
-        state::collect_workspace_meta(),
+        options,
         Config::default()
             .with_window(
                 WindowBuilder::new()
@@ -26,46 +64,163 @@ fn main() {
             )
             .with_custom_head(r#"<script src="https://cdn.tailwindcss.com"></script>"#.into()),
     );
+
+    ExitCode::SUCCESS
 }
 
-fn app(cx: Scope<Metadata>) -> Element {
+#[cfg(not(feature = "gui"))]
+fn launch_window(_options: WorkspaceOptions) -> ExitCode {
+    eprintln!("error: built without the `gui` feature, so pass a subcommand like `check`");
+    ExitCode::FAILURE
+}
+
+/// Load the workspace off the UI thread, so the window shows up straight away
+fn app(cx: Scope<WorkspaceOptions>) -> Element {
     use_init_atom_root(cx);
     use_init_signal_rt(cx);
 
-    let graph = use_state(cx, || CrateGraph::new(&cx.props));
-    let ignored_crates = use_state(cx, || {
-        cx.props
-            .packages
-            .iter()
-            .filter(|package| graph.crates.contains(&package.id) && package.publish == Some(vec![]))
-            .map(|package| package.id.clone())
-            .collect::<HashSet<PackageId>>()
+    let loaded = use_future(cx, (), |_| watch::load_graph(cx.props.clone()));
+
+    match loaded.value() {
+        Some(Ok(graph)) => render! { Workspace { initial: graph } },
+        Some(Err(err)) => render! {
+            section { class: "py-12 font-mono container px-4 mx-auto text-red-500", "Failed to load the workspace: {err}" }
+        },
+        None => render! {
+            section { class: "py-12 font-mono container px-4 mx-auto text-gray-500", "Loading workspace..." }
+        },
+    }
+}
+
+#[inline_props]
+fn Workspace<'a>(cx: Scope<'a>, initial: &'a CrateGraph) -> Element {
+    let graph = use_state(cx, || (*initial).clone());
+    let ignored_crates = use_state(cx, || graph.default_ignored());
+    let selected = use_state(cx, HashSet::<String>::new);
+    let show_graph = use_state(cx, || false);
+
+    // Start the checkboxes from the defaults saved in the workspace config, once per window
+    let set_allow_dirty = use_set(cx, ALLOW_DIRTY);
+    let set_dry_run = use_set(cx, DRY_RUN);
+    cx.use_hook(|| {
+        set_allow_dirty(initial.config.allow_dirty);
+        set_dry_run(initial.config.dry_run);
+    });
+
+    let set_working_tree = use_set(cx, WORKING_TREE).clone();
+    use_future(cx, (), |_| {
+        watch::watch_workspace(graph.clone(), ignored_crates.clone(), set_working_tree)
+    });
+
+    // Loads and reloads skip cargo's resolver, so whenever the graph comes straight from the
+    // manifests, fetch the resolved dependency versions in the background and swap them in
+    use_future(cx, (graph.options.resolve,), |(resolved,)| {
+        let graph = graph.clone();
+        let ignored_crates = ignored_crates.clone();
+
+        async move {
+            if !resolved {
+                watch::resolve_dependencies(graph, ignored_crates).await;
+            }
+        }
     });
 
+    let tab = |active: bool| match active {
+        true => "mr-4 font-semibold text-gray-900 border-b-2 border-gray-900",
+        false => "mr-4 font-medium text-gray-500 hover:text-gray-700",
+    };
+    let (crates_tab, graph_tab) = (tab(!**show_graph), tab(**show_graph));
+
     render! {
         section { class: "py-12 bg-white font-mono container px-4 mx-auto max-w-screen-xl",
 
-            header::Header { meta: cx.props, graph: graph }
-            config_banner::GlobalConfig {}
+            header::Header { meta: &graph.meta, graph: graph }
 
-            div {
-                for (id , _) in graph.sorted.iter().filter(|id| !ignored_crates.contains(&id.0)) {
-                    rows::RowItem { graph: graph, id: id.clone() }
-                }
+            div { class: "mb-4",
+                button { class: "{crates_tab}", onclick: move |_| show_graph.set(false), "Crates" }
+                button { class: "{graph_tab}", onclick: move |_| show_graph.set(true), "Graph" }
+            }
+
+            if **show_graph {
+                rsx! { graph_view::GraphView { graph: graph, ignored_crates: ignored_crates } }
+            } else {
+                rsx! { Releases { graph: graph, ignored_crates: ignored_crates, selected: selected } }
+            }
+        }
+    }
+}
+
+#[inline_props]
+fn Releases<'a>(
+    cx: Scope<'a>,
+    graph: &'a UseState<CrateGraph>,
+    ignored_crates: &'a UseState<HashSet<PackageId>>,
+    selected: &'a UseState<HashSet<String>>,
+) -> Element {
+    // One section per version group, in release order within each, then the ungrouped crates
+    let mut groups = BTreeMap::<String, Vec<&PackageId>>::new();
+    let mut ungrouped = vec![];
 
-                if !ignored_crates.is_empty() {
+    for (id, _) in graph
+        .sorted
+        .iter()
+        .filter(|id| !ignored_crates.contains(&id.0))
+    {
+        match graph.config.group_of(&graph.get_crate(id).name) {
+            Some((name, group)) => {
+                let policy = if group.lockstep {
+                    "lockstep"
+                } else {
+                    "independent"
+                };
+                groups
+                    .entry(format!("{name} ({policy})"))
+                    .or_default()
+                    .push(id);
+            }
+            None => ungrouped.push(id),
+        }
+    }
+
+    // Without any groups configured there's just the one list, so no headings
+    let show_headings = !groups.is_empty();
+    let mut sections = groups.into_iter().collect::<Vec<_>>();
+    if !ungrouped.is_empty() {
+        sections.push(("ungrouped".to_string(), ungrouped));
+    }
+
+    render! {
+        config_banner::GlobalConfig { graph: graph, ignored_crates: ignored_crates, selected: selected }
+
+        div {
+            sections.iter().map(|(title, ids)| rsx! {
+                if show_headings {
                     rsx! {
-                        div {
-                            class: "w-full border-t border-gray-200 mt-4 text-gray-500 text-center",
-                            "ignored"
-                        }
+                        div { class: "w-full border-b border-gray-200 mb-4 text-gray-600 font-semibold", "{title}" }
                     }
                 }
 
-                for id in ignored_crates.iter() {
-                    rows::RowItem { graph: graph, id: id.clone() }
+                // Keyed by name, since ids change with the version and rows keep their state
+                ids.iter().map(|id| {
+                    let name = &graph.get_crate(id).name;
+                    rsx! {
+                        rows::RowItem { key: "{name}", graph: graph, ignored_crates: ignored_crates, selected: selected, id: (*id).clone() }
+                    }
+                })
+            })
+
+            if !ignored_crates.is_empty() {
+                rsx! {
+                    div {
+                        class: "w-full border-t border-gray-200 mt-4 text-gray-500 text-center",
+                        "ignored"
+                    }
                 }
             }
+
+            for id in ignored_crates.iter() {
+                rows::RowItem { graph: graph, ignored_crates: ignored_crates, selected: selected, id: id.clone() }
+            }
         }
     }
 }
@@ -0,0 +1,138 @@
+//! Breaking changes that hide in the manifest rather than the code: features and MSRV
+use crate::api_diff::{last_published_version, published_manifest};
+use crate::config::WorkspaceConfig;
+use crate::rows::req_minimum;
+use crate::state::BumpLevel;
+use anyhow::Context;
+use cargo_metadata::{
+    camino::Utf8Path,
+    semver::{Version, VersionReq},
+    MetadataCommand, Package,
+};
+use std::collections::BTreeSet;
+
+pub struct ManifestDiff {
+    /// The published version the working copy was compared against
+    pub baseline: Version,
+    pub added_features: Vec<String>,
+    pub removed_features: Vec<String>,
+
+    /// The `default` feature before and after, if it changed
+    pub default_features: Option<(Vec<String>, Vec<String>)>,
+
+    /// The `rust-version` before and after, if it was raised
+    pub rust_version: Option<(Option<VersionReq>, VersionReq)>,
+}
+
+impl ManifestDiff {
+    pub fn is_empty(&self) -> bool {
+        self.added_features.is_empty() && !self.is_breaking()
+    }
+
+    /// Dropping a feature, touching the defaults, or raising the MSRV all break someone's build
+    pub fn is_breaking(&self) -> bool {
+        !self.removed_features.is_empty()
+            || self.default_features.is_some()
+            || self.rust_version.is_some()
+    }
+
+    pub fn required_bump(&self) -> BumpLevel {
+        BumpLevel::for_change(
+            &self.baseline,
+            self.is_breaking(),
+            !self.added_features.is_empty(),
+        )
+    }
+
+    /// Human readable list of changes, each flagged with whether it's breaking
+    pub fn changes(&self) -> Vec<(bool, String)> {
+        let mut out = vec![];
+
+        for feature in &self.removed_features {
+            out.push((true, format!("removed feature `{feature}`")));
+        }
+
+        if let Some((before, after)) = &self.default_features {
+            let (before, after) = (before.join(", "), after.join(", "));
+            out.push((true, format!("default features [{before}] → [{after}]")));
+        }
+
+        if let Some((before, after)) = &self.rust_version {
+            let before = before.as_ref().map(ToString::to_string);
+            let before = before.as_deref().unwrap_or("unset");
+            out.push((true, format!("rust-version {before} → {after}")));
+        }
+
+        for feature in &self.added_features {
+            out.push((false, format!("added feature `{feature}`")));
+        }
+
+        out
+    }
+}
+
+/// Compare the working copy's manifest against the one from the last published release
+pub async fn collect_manifest_diff(
+    package: &Package,
+    index: &str,
+    config: &WorkspaceConfig,
+    workspace_root: &Utf8Path,
+    target_dir: &Utf8Path,
+) -> anyhow::Result<ManifestDiff> {
+    let scratch = target_dir.join("easy-release");
+    let baseline = last_published_version(package, index).await?;
+    let manifest = published_manifest(package, &baseline, config, workspace_root, &scratch).await?;
+
+    // Going through cargo means workspace inheritance and implicit features are resolved the same
+    // way on both sides
+    let name = package.name.clone();
+    let old = tokio::task::spawn_blocking(move || {
+        let meta = MetadataCommand::new()
+            .manifest_path(&manifest)
+            .no_deps()
+            .exec()?;
+
+        meta.packages
+            .into_iter()
+            .find(|p| p.name == name)
+            .with_context(|| format!("{manifest} doesn't contain {name}"))
+    })
+    .await??;
+
+    Ok(diff_manifests(&old, package, baseline))
+}
+
+fn diff_manifests(old: &Package, new: &Package, baseline: Version) -> ManifestDiff {
+    let old_features = old.features.keys().collect::<BTreeSet<_>>();
+    let new_features = new.features.keys().collect::<BTreeSet<_>>();
+
+    let default_features = |package: &Package| {
+        let mut features = package.features.get("default").cloned().unwrap_or_default();
+        features.sort();
+        features
+    };
+
+    let (old_default, new_default) = (default_features(old), default_features(new));
+
+    let rust_version = match (&old.rust_version, &new.rust_version) {
+        (None, Some(new)) => Some((None, new.clone())),
+        (Some(old), Some(new)) if req_minimum(new) > req_minimum(old) => {
+            Some((Some(old.clone()), new.clone()))
+        }
+        _ => None,
+    };
+
+    ManifestDiff {
+        baseline,
+        added_features: new_features
+            .difference(&old_features)
+            .map(|f| f.to_string())
+            .collect(),
+        removed_features: old_features
+            .difference(&new_features)
+            .map(|f| f.to_string())
+            .collect(),
+        default_features: (old_default != new_default).then_some((old_default, new_default)),
+        rust_version,
+    }
+}
@@ -0,0 +1,124 @@
+//! Cached lookups of published versions in sparse registry indexes, crates.io's by default
+use cargo_metadata::semver::Version;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+pub const CRATES_IO: &str = "registry+https://github.com/rust-lang/crates.io-index";
+
+/// crates.io's sparse index, used unless `--registry` names another one
+pub const SPARSE_INDEX: &str = "https://index.crates.io";
+
+/// Index entries we've already fetched this session, keyed by index URL and lowercase crate name
+static INDEX_CACHE: Mutex<BTreeMap<(String, String), Arc<IndexEntry>>> =
+    Mutex::new(BTreeMap::new());
+
+/// Set by `--offline`, after which only entries that are already cached are returned
+static OFFLINE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_offline(offline: bool) {
+    OFFLINE.store(offline, Ordering::Relaxed);
+}
+
+/// Every version of a crate that's been published, as listed in the index
+#[derive(Debug)]
+pub struct IndexEntry {
+    pub versions: Vec<IndexVersion>,
+}
+
+#[derive(Debug)]
+pub struct IndexVersion {
+    pub version: Version,
+    pub yanked: bool,
+}
+
+/// One line of a sparse index file. We only care about a couple of the fields
+#[derive(Deserialize)]
+struct IndexLine {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+impl IndexEntry {
+    /// The newest version that's neither yanked nor a pre-release
+    pub fn latest(&self) -> Option<&Version> {
+        self.versions
+            .iter()
+            .filter(|v| !v.yanked && v.version.pre.is_empty())
+            .map(|v| &v.version)
+            .max()
+    }
+
+    pub fn is_yanked(&self, version: &Version) -> bool {
+        self.versions
+            .iter()
+            .any(|v| v.yanked && v.version == *version)
+    }
+
+    pub fn is_published(&self, version: &Version) -> bool {
+        self.versions.iter().any(|v| v.version == *version)
+    }
+}
+
+/// Fetch a crate's entry in the crates.io index
+pub async fn index_entry(name: &str) -> anyhow::Result<Arc<IndexEntry>> {
+    registry_entry(SPARSE_INDEX, name).await
+}
+
+/// Fetch a crate's entry in the sparse index at `index`, hitting the network only the first time
+/// it's asked for
+pub async fn registry_entry(index: &str, name: &str) -> anyhow::Result<Arc<IndexEntry>> {
+    let key = (index.to_string(), name.to_lowercase());
+    let name = &key.1;
+
+    if let Some(entry) = INDEX_CACHE.lock().unwrap().get(&key) {
+        return Ok(entry.clone());
+    }
+
+    if OFFLINE.load(Ordering::Relaxed) {
+        anyhow::bail!("can't look up {name} in the index while offline");
+    }
+
+    let body = reqwest::Client::builder()
+        .user_agent("cargo-easy-release (https://github.com/jkelleyrtp/cargo-easy-release)")
+        .build()?
+        .get(format!("{index}/{}", index_path(name)))
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    let versions = body
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let line: IndexLine = serde_json::from_str(line)?;
+            Ok(IndexVersion {
+                version: line.vers.parse()?,
+                yanked: line.yanked,
+            })
+        })
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    let entry = Arc::new(IndexEntry { versions });
+    INDEX_CACHE.lock().unwrap().insert(key, entry.clone());
+
+    Ok(entry)
+}
+
+/// Where a crate lives in the index, following cargo's directory layout for names of each length
+fn index_path(name: &str) -> String {
+    match name.len() {
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    }
+}
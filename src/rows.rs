@@ -1,35 +1,256 @@
-use crate::state::CrateGraph;
+use crate::api_diff::{collect_api_diff, ApiDiff};
+use crate::cargo_util::cargo_bin;
+use crate::changelog::{self, collect_changelog, Changelog};
+use crate::config::WorkspaceConfig;
+use crate::diff::DiffPreview;
+use crate::manifest_diff::{collect_manifest_diff, ManifestDiff};
+use crate::state::{update_config, BumpLevel, CrateGraph, WorkspaceOptions};
 use crate::templates;
+use crate::watch::reload_workspace;
 use cargo_metadata::{
     camino::Utf8PathBuf,
-    semver::{Version, VersionReq},
-    DependencyKind, Metadata, Package, PackageId,
+    semver::{BuildMetadata, Op, Version, VersionReq},
+    Dependency, DependencyKind, Metadata, Package, PackageId,
 };
 use dioxus::prelude::*;
-use dioxus_signals::{use_signal, Signal};
+use dioxus_signals::use_signal;
 use fermi::use_read;
+use std::collections::HashSet;
 use tokio::process::Command;
 
 #[derive(Props)]
 pub struct RowProps<'a> {
-    graph: &'a CrateGraph,
+    graph: &'a UseState<CrateGraph>,
+    ignored_crates: &'a UseState<HashSet<PackageId>>,
+    selected: &'a UseState<HashSet<String>>,
     id: PackageId,
 }
 
 pub fn RowItem<'a>(cx: Scope<'a, RowProps<'a>>) -> Element {
-    let RowProps { graph, id } = cx.props;
+    let RowProps {
+        graph,
+        ignored_crates,
+        selected,
+        id,
+    } = cx.props;
     let meta = &graph.meta;
 
     let manifest_path = use_signal(cx, || get_manifest_path(&graph.meta, id));
     let allow_dirty = use_read(cx, crate::state::ALLOW_DIRTY).clone();
     let dry_run = use_read(cx, crate::state::DRY_RUN).clone();
-    let release_crate = move || run_release(manifest_path, allow_dirty, dry_run);
+    let pre_release = use_read(cx, crate::state::PRE_RELEASE);
+    let working_tree = *use_read(cx, crate::state::WORKING_TREE);
+    let release_crate = move || {
+        let graph = graph.get().clone();
+        let id = id.clone();
+        let manifest_path = manifest_path.get().clone();
+
+        for (_, dep) in graph.ignored_dependencies(&[id.clone()], ignored_crates) {
+            let (package, dep) = (graph.get_crate(&id), graph.get_crate(&dep));
+            println!("Warning: {} depends on ignored {}", package.name, dep.name);
+        }
+
+        async move {
+            // The status shown in the row may be a poll behind, so look again before publishing
+            if !allow_dirty {
+                let status = graph.working_tree_status(&id).await?;
+
+                if !status.is_clean() {
+                    let name = &graph.get_crate(&id).name;
+                    anyhow::bail!("{name} has uncommitted changes ({})", status.summary());
+                }
+            }
+
+            run_release(manifest_path, allow_dirty, dry_run, graph.options.clone()).await?;
+
+            if !dry_run {
+                graph.record_release(&[id.clone()]).await?;
+            }
+
+            anyhow::Ok(())
+        }
+    };
     let package = graph.get_crate(id);
+    let is_selected = selected.contains(&package.name);
+    let local_deps = package_local_deps(package);
+    let bumped = use_future(cx, (id.clone(), package.version.clone()), |(id, _)| {
+        let graph = graph.get().clone();
+        async move { graph.has_unreleased_bump(&id).await }
+    });
+    let mut blockers = release_blockers(package, &graph.config, bumped.value() == Some(&true));
+
+    // Re-read the working tree whenever the crate's version moves, which is when we write to it,
+    // and whenever the watcher sees the workspace's status change
+    let git_key = (id.clone(), package.version.clone(), working_tree);
+    let git_status = use_future(cx, git_key.clone(), |(id, _, _)| {
+        let graph = graph.get().clone();
+        async move { graph.working_tree_status(&id).await }
+    });
+    let (git_color, git_summary, git_files) = match git_status.value() {
+        None => ("text-gray-400", "checking...".to_string(), String::new()),
+        Some(Err(err)) => ("text-gray-400", "unavailable".to_string(), err.to_string()),
+        Some(Ok(status)) => {
+            let files = [&status.staged, &status.modified, &status.untracked]
+                .into_iter()
+                .flatten()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            match status.is_clean() {
+                true => ("text-green-600", status.summary(), files),
+                false => ("text-yellow-600", status.summary(), files),
+            }
+        }
+    };
+
+    let changes = use_future(cx, git_key, |(id, _, _)| {
+        let graph = graph.get().clone();
+        async move { graph.changes_since_release(&id).await }
+    });
+    let (changes_color, changes_summary) = match changes.value() {
+        None => ("text-gray-400", "checking tags...".to_string()),
+        Some(Err(_)) => ("text-gray-400", "no git history".to_string()),
+        Some(Ok(None)) => ("text-gray-500", "never tagged".to_string()),
+        Some(Ok(Some(changes))) if !changes.needs_release() => (
+            "text-teal-600",
+            format!("no release needed since {}", changes.tag),
+        ),
+        Some(Ok(Some(changes))) => (
+            "text-gray-500",
+            format!("{} commits since {}", changes.commits, changes.tag),
+        ),
+    };
+
+    if !allow_dirty && matches!(git_status.value(), Some(Ok(status)) if !status.is_clean()) {
+        blockers.push("uncommitted changes".to_string());
+    }
 
-    // Running a minor patch implies moving every transitive dep to the next minor version of this crate.
-    // So bumping a "core" crate would involve bumping all the crates that depend on it too.
-    let minor_patch = |_| {
-        //
+    let blocked_reason = blockers.join(", ");
+
+    // Crates with `publish = false` in their manifest can't be brought back from the UI
+    let is_ignored = ignored_crates.contains(id);
+    let unpublishable = package.publish == Some(vec![]);
+    let ignore_label = if is_ignored { "Unignore" } else { "Ignore" };
+    let toggle_ignored = move |_| {
+        let name = package.name.clone();
+
+        ignored_crates.with_mut(|ignored| {
+            if !ignored.remove(id) {
+                ignored.insert(id.clone());
+            }
+        });
+
+        update_config(graph, |config| {
+            if is_ignored {
+                config.ignored.remove(&name);
+                if let Some(krate) = config.crates.get_mut(&name) {
+                    krate.publish = None;
+                }
+            } else {
+                config.ignored.insert(name);
+            }
+        });
+    };
+
+    let api_diff = use_state(cx, || None::<Result<ApiDiff, String>>);
+    let manifest_diff = use_state(cx, || None::<Result<ManifestDiff, String>>);
+    let checking_api = use_state(cx, || false);
+    let api_label = if **checking_api {
+        "Diffing API..."
+    } else {
+        "API diff"
+    };
+    let check_api = move |_| {
+        let package = package.clone();
+        let index = graph.publish_index();
+        let config = graph.config.clone();
+        let workspace_root = meta.workspace_root.clone();
+        let target_dir = meta.target_directory.clone();
+        let api_diff = api_diff.clone();
+        let manifest_diff = manifest_diff.clone();
+        let checking_api = checking_api.clone();
+
+        checking_api.set(true);
+        cx.spawn(async move {
+            let index = match index {
+                Ok(index) => index,
+                Err(err) => {
+                    manifest_diff.set(Some(Err(err.to_string())));
+                    api_diff.set(Some(Err(err.to_string())));
+                    checking_api.set(false);
+                    return;
+                }
+            };
+
+            let diff =
+                collect_manifest_diff(&package, &index, &config, &workspace_root, &target_dir)
+                    .await;
+            manifest_diff.set(Some(diff.map_err(|err| err.to_string())));
+
+            let diff =
+                collect_api_diff(&package, &index, &config, &workspace_root, &target_dir).await;
+            api_diff.set(Some(diff.map_err(|err| err.to_string())));
+            checking_api.set(false);
+        });
+    };
+
+    // Conventional commits since the last tag, for the changelog and the bump they imply
+    let commit_log = use_future(cx, (id.clone(), package.version.clone()), |(id, _)| {
+        let graph = graph.get().clone();
+        async move { collect_changelog(&graph, &id).await }
+    });
+    let pending_changelog = commit_log
+        .value()
+        .and_then(|changelog| changelog.as_ref().ok())
+        .filter(|changelog| !changelog.entries.is_empty());
+    let editing_notes = use_state(cx, || false);
+    let generated_notes = match pending_changelog {
+        Some(changelog) => changelog.render(),
+        None => format!("## {}\n", package.version),
+    };
+
+    let bump_policy = graph.config.bump_policy(&package.name);
+    let required_bump = required_bump(
+        package,
+        bump_policy,
+        api_diff.get(),
+        manifest_diff.get(),
+        pending_changelog,
+    );
+
+    // Bumping a "core" crate also moves the requirement of every crate that depends on it
+    let bump_warning = use_state(cx, || None::<BumpLevel>);
+    let bump = move |level: BumpLevel| {
+        if let Some(required) = required_bump {
+            // Make the user confirm a bump that's smaller than the API change requires
+            if level < required && **bump_warning != Some(level) {
+                bump_warning.set(Some(level));
+                return;
+            }
+        }
+
+        bump_warning.set(None);
+
+        let pre = Some(pre_release.as_str()).filter(|pre| !pre.is_empty());
+        if let Err(err) = graph.bump(id, level, pre) {
+            println!("Failed to bump {}: {err}", package.name);
+            return;
+        }
+
+        let (graph, ignored_crates) = (graph.clone(), ignored_crates.clone());
+        cx.spawn(async move { reload_workspace(&graph, &ignored_crates).await });
+    };
+
+    // Moving a pre-release on to the next one only makes sense while there is one
+    let bump_levels = match package.version.pre.is_empty() {
+        true => vec![BumpLevel::Patch, BumpLevel::Minor, BumpLevel::Major],
+        false => vec![
+            BumpLevel::Pre,
+            BumpLevel::Patch,
+            BumpLevel::Minor,
+            BumpLevel::Major,
+        ],
     };
 
     cx.render(rsx! {
@@ -37,43 +258,170 @@ pub fn RowItem<'a>(cx: Scope<'a, RowProps<'a>>) -> Element {
             div { class: "w-full",
                 h3 { class: "text-md mb-2 text-gray-800 group-hover:text-gray-900 font-semibold transition duration-200 font-mono flex flex-row justify-between",
                     div { class: "flex flex-row",
+                        input {
+                            class: "mr-2",
+                            r#type: "checkbox",
+                            checked: "{is_selected}",
+                            onchange: move |_| selected.with_mut(|selected| {
+                                if !selected.remove(&package.name) {
+                                    selected.insert(package.name.clone());
+                                }
+                            })
+                        }
                         span { "{package.name}" }
                         span { class: "text-gray-500 ml-2", "{package.version}" }
+                        span { class: "{git_color} ml-2 text-xs self-center", title: "{git_files}", "git: {git_summary}" }
+                        span { class: "{changes_color} ml-2 text-xs self-center", "{changes_summary}" }
                     }
                     div {
                         button {
                             class: "inline-flex ml-auto items-center font-medium leading-6 group-hover:text-green-600 transition duration-200 ",
-                            onclick: move |_| {},
-                            span { class: "mr-2", "Patch" }
+                            disabled: "{checking_api}",
+                            onclick: check_api,
+                            span { class: "mr-2", "{api_label}" }
                         }
                         button {
-                            class: "inline-flex ml-auto items-center font-medium leading-6 group-hover:text-green-600 transition duration-200 ",
-                            onclick: minor_patch,
-                            span { class: "mr-2", "Minor" }
+                            class: "inline-flex ml-auto items-center font-medium leading-6 text-gray-500 hover:text-gray-700 transition duration-200",
+                            disabled: "{unpublishable}",
+                            onclick: toggle_ignored,
+                            span { class: "mr-2", "{ignore_label}" }
                         }
-
                         button {
-                            class: "inline-flex ml-auto items-center font-medium leading-6 text-green-500 group-hover:text-green-600 transition duration-200 ",
-                            onclick: move |_| cx.spawn(release_crate()),
-                            span { class: "mr-2", "Release" }
+                            class: "inline-flex ml-auto items-center font-medium leading-6 group-hover:text-green-600 transition duration-200 ",
+                            onclick: move |_| editing_notes.set(!editing_notes.get()),
+                            span { class: "mr-2", "Release notes" }
+                        }
+                        bump_levels.iter().map(|&level| {
+                            let highlight = match required_bump == Some(level) {
+                                true => "text-blue-600 underline",
+                                false => "",
+                            };
+
+                            rsx! {
+                                button {
+                                    class: "inline-flex ml-auto items-center font-medium leading-6 group-hover:text-green-600 transition duration-200 {highlight}",
+                                    onclick: move |_| bump(level),
+                                    span { class: "mr-2", "{level.label()}" }
+                                }
+                            }
+                        })
+
+                        if blockers.is_empty() {
+                            rsx! {
+                                button {
+                                    class: "inline-flex ml-auto items-center font-medium leading-6 text-green-500 group-hover:text-green-600 transition duration-200 ",
+                                    onclick: move |_| cx.spawn(async move {
+                                        if let Err(err) = release_crate().await {
+                                            println!("{err}");
+                                        }
+                                    }),
+                                    span { class: "mr-2", "Release" }
+                                }
+                            }
+                        } else {
+                            rsx! {
+                                button {
+                                    class: "inline-flex ml-auto items-center font-medium leading-6 text-gray-400 cursor-not-allowed",
+                                    disabled: true,
+                                    title: "Blocked: {blocked_reason}",
+                                    span { class: "mr-2", "Release" }
+                                }
+                            }
                         }
                     }
                 }
                 div { class: "flex flex-row justify-between",
-                    PackageChecklist { package: package }
-                    CrateDeps { graph: graph, meta: meta, id: id.clone() }
+                    PackageChecklist { package: package, local_deps: local_deps }
+                    CrateDeps { graph: graph.get(), meta: meta, id: id.clone() }
+                }
+
+                if let (Some(level), Some(required)) = (**bump_warning, required_bump) {
+                    rsx! {
+                        div { class: "text-xs text-yellow-600 mt-2",
+                            "⚠️ The API, manifest and commit changes need a {required.label()} bump. Click {level.label()} again to bump anyway."
+                        }
+                    }
+                }
+
+                if **editing_notes {
+                    rsx! {
+                        ReleaseNotes {
+                            path: changelog::changelog_path(graph, id),
+                            version: package.version.clone(),
+                            generated: generated_notes,
+                            open: editing_notes,
+                        }
+                    }
+                }
+
+                match manifest_diff.get() {
+                    Some(Ok(diff)) => rsx! { ManifestDiffView { diff: diff } },
+                    Some(Err(err)) => rsx! { div { class: "text-xs text-red-500 mt-2", "Manifest diff failed: {err}" } },
+                    None => rsx! { Fragment {} },
+                }
+
+                match api_diff.get() {
+                    Some(Ok(diff)) => rsx! { ApiDiffView { diff: diff } },
+                    Some(Err(err)) => rsx! { div { class: "text-xs text-red-500 mt-2", "API diff failed: {err}" } },
+                    None => rsx! { Fragment {} },
                 }
             }
         }
     })
 }
 
-async fn run_release(manifest_path: Signal<Utf8PathBuf>, allow_dirty: bool, dry_run: bool) {
-    let mut cmd = Command::new("cargo");
+/// The bump the API, manifest and commit changes call for, unless the working copy is already
+/// bumped far enough
+///
+/// A crate's bump policy from the workspace config raises the floor of the suggestion.
+fn required_bump(
+    package: &Package,
+    policy: Option<BumpLevel>,
+    api_diff: &Option<Result<ApiDiff, String>>,
+    manifest_diff: &Option<Result<ManifestDiff, String>>,
+    changelog: Option<&Changelog>,
+) -> Option<BumpLevel> {
+    let api = api_diff
+        .as_ref()
+        .and_then(|diff| diff.as_ref().ok())
+        .map(|diff| (&diff.baseline, diff.required_bump()));
+
+    let manifest = manifest_diff
+        .as_ref()
+        .and_then(|diff| diff.as_ref().ok())
+        .map(|diff| (&diff.baseline, diff.required_bump()));
+
+    let commits = changelog.and_then(Changelog::required_bump);
+
+    let (baseline, required) = api
+        .into_iter()
+        .chain(manifest)
+        .chain(commits)
+        .max_by_key(|(_, required)| *required)?;
+    let required = required.max(policy.unwrap_or(BumpLevel::Patch));
+
+    match BumpLevel::between(baseline, &package.version) {
+        Some(pending) if pending >= required => None,
+        _ => Some(required),
+    }
+}
+
+pub async fn run_release(
+    manifest_path: Utf8PathBuf,
+    allow_dirty: bool,
+    dry_run: bool,
+    options: WorkspaceOptions,
+) -> anyhow::Result<()> {
+    let mut cmd = Command::new(cargo_bin());
 
     cmd.arg("publish")
         .arg("--manifest-path")
-        .arg(&*manifest_path.get());
+        .arg(&manifest_path);
+    cmd.args(options.cargo_args());
+
+    if let Some(registry) = &options.registry {
+        cmd.arg("--registry").arg(registry);
+    }
 
     if allow_dirty {
         cmd.arg("--allow-dirty");
@@ -83,13 +431,36 @@ async fn run_release(manifest_path: Signal<Utf8PathBuf>, allow_dirty: bool, dry_
         cmd.arg("--dry-run");
     }
 
-    cmd.spawn().unwrap().wait().await.unwrap();
+    let status = cmd.spawn()?.wait().await?;
+
+    if !status.success() {
+        anyhow::bail!("cargo publish failed for {manifest_path}");
+    }
+
+    Ok(())
 }
 
 #[inline_props]
-fn PackageChecklist<'a>(cx: Scope<'a>, package: &'a Package) -> Element {
+fn PackageChecklist<'a>(
+    cx: Scope<'a>,
+    package: &'a Package,
+    local_deps: Vec<&'a Dependency>,
+) -> Element {
     render! {
         div { class: "text-gray-500 text-xs flex flex-col",
+            div {
+                if local_deps.is_empty() {
+                    render! { "✅ Publishable dependencies" }
+                } else {
+                    render! (
+                        "❌ Unpublishable dependencies: ",
+                        local_deps.iter().map(|dep| {
+                            let kind = if dep.path.is_some() { "path" } else { "git" };
+                            render!( "{dep.name} ({kind}), " )
+                        })
+                    )
+                }
+            }
             div {
                 if package.keywords.is_empty() {
                     render! { "❌ Missing keywords" }
@@ -128,48 +499,162 @@ fn PackageChecklist<'a>(cx: Scope<'a>, package: &'a Package) -> Element {
     }
 }
 
-fn package_has_local_deps(package: &cargo_metadata::Package) -> bool {
-    let package_has_local_deps = package.dependencies.iter().any(|dep| {
-        // If the source is a git repo, then it's a local dep
-        if dep
-            .source
-            .as_ref()
-            .map(|s| s.starts_with("git+"))
-            .unwrap_or(false)
-        {
-            return true;
+/// Collect the normal and build dependencies that crates.io would reject.
+///
+/// Dev-dependencies are stripped by `cargo publish`, and a git or path dependency is fine as long
+/// as it also carries a version requirement to fall back on.
+pub fn package_local_deps(package: &Package) -> Vec<&Dependency> {
+    package
+        .dependencies
+        .iter()
+        .filter(|dep| matches!(dep.kind, DependencyKind::Normal | DependencyKind::Build))
+        .filter(|dep| {
+            // If the source is a git repo, then it's a local dep
+            let is_git = dep
+                .source
+                .as_ref()
+                .map(|s| s.starts_with("git+"))
+                .unwrap_or(false);
+
+            // A path or git dep with no version has nothing to publish against
+            (is_git || dep.path.is_some()) && dep.req.comparators.is_empty()
+        })
+        .collect()
+}
+
+/// Manifest fields crates.io insists on. Missing ones colour the crate as blocked in the graph,
+/// but are left for `cargo publish` to report rather than holding back a release
+///
+/// Like the release blockers, each check can be listed under `skip-checks`.
+pub fn missing_metadata(package: &Package, config: &WorkspaceConfig) -> Vec<String> {
+    let mut missing = vec![];
+    let check = |name: &str| !config.skips_check(&package.name, name);
+
+    if check("description") && package.description.is_none() {
+        missing.push("missing description".to_string());
+    }
+
+    if check("license") && package.license.is_none() && package.license_file.is_none() {
+        missing.push("missing license".to_string());
+    }
+
+    missing
+}
+
+/// Everything that stops this crate from being released as it stands
+///
+/// Each check has a name that can be listed under `skip-checks` in the workspace config. `bumped`
+/// says whether the crate's version is ahead of its last release, see
+/// [`CrateGraph::has_unreleased_bump`].
+pub fn release_blockers(package: &Package, config: &WorkspaceConfig, bumped: bool) -> Vec<String> {
+    let mut blockers = vec![];
+    let check = |name: &str| !config.skips_check(&package.name, name);
+
+    // A bumped version is a new release, so it needs its own entry
+    let wants_entry = config.require_changelog && bumped;
+    if check("changelog") && wants_entry && !changelog::has_entry(package) {
+        blockers.push(format!("no changelog entry for {}", package.version));
+    }
+
+    if check("local-deps") {
+        for dep in package_local_deps(package) {
+            let kind = if dep.path.is_some() { "path" } else { "git" };
+            blockers.push(format!(
+                "{} is a {kind} dependency without a version",
+                dep.name
+            ));
+        }
+    }
+
+    blockers
+}
+
+#[inline_props]
+fn ApiDiffView<'a>(cx: Scope<'a>, diff: &'a ApiDiff) -> Element {
+    if diff.is_empty() {
+        return render! {
+            div { class: "text-xs text-gray-500 mt-2", "No public API changes since {diff.baseline}" }
+        };
+    }
+
+    render! {
+        div { class: "text-xs mt-2 font-mono",
+            div { class: "text-gray-500 mb-1", "Public API changes since {diff.baseline}" }
+            diff.removed.iter().map(|item| rsx! { div { class: "text-red-600", "- {item}" } })
+            diff.changed.iter().map(|(old, new)| rsx! {
+                div { class: "text-yellow-600", "~ {old}" }
+                div { class: "text-yellow-600 pl-2", "→ {new}" }
+            })
+            diff.added.iter().map(|item| rsx! { div { class: "text-green-600", "+ {item}" } })
         }
+    }
+}
 
-        // If the source is a path with no version, then it's a local dep
-        dep.path.is_some() && dep.kind == DependencyKind::Normal && dep.req.comparators.is_empty()
+/// Edit the crate's changelog section for its current version, starting from the existing
+/// section or the one generated from commits, and save it with a preview of the change
+#[inline_props]
+fn ReleaseNotes<'a>(
+    cx: Scope<'a>,
+    path: Utf8PathBuf,
+    version: Version,
+    generated: String,
+    open: &'a UseState<bool>,
+) -> Element {
+    let before = use_state(cx, || std::fs::read_to_string(path).unwrap_or_default());
+    let draft = use_state(cx, || match changelog::find_section(before, version) {
+        Some(range) => before.get()[range].trim_end().to_string(),
+        None => generated.clone(),
     });
+    let after = changelog::with_section(before, version, draft);
 
-    package_has_local_deps
-}
-
-fn collect_api_diff(id: &PackageId, graph: &CrateGraph) -> String {
-    use public_api::Options;
-    let id = id.to_string();
-    let crate_name = id.split_ascii_whitespace().next().unwrap();
-    let path = graph
-        .meta
-        .target_directory
-        .to_path_buf()
-        .join("doc")
-        .join(format!("{crate_name}.json"));
-    let maybe = || {
-        let new =
-            public_api::PublicApi::from_rustdoc_json(path.clone(), Options::default()).ok()?;
-
-        let old =
-            public_api::PublicApi::from_rustdoc_json(path.clone(), Options::default()).ok()?;
-
-        Some(format!(
-            "{:#?}",
-            public_api::diff::PublicApiDiff::between(old, new)
-        ))
+    let save = move |_| {
+        let after = changelog::with_section(before, version, draft);
+
+        match std::fs::write(path, &after) {
+            Ok(()) => {
+                before.set(after);
+                open.set(false);
+            }
+            Err(err) => println!("Failed to write {path}: {err}"),
+        }
     };
-    maybe().unwrap_or_default()
+
+    render! {
+        div { class: "mt-2 p-2 bg-white rounded-md border border-gray-200",
+            div { class: "flex flex-row justify-between mb-2 text-sm",
+                div { class: "text-gray-500", "{path}" }
+                div {
+                    button { class: "mr-4 font-medium text-green-500 hover:text-green-600", onclick: save, "Save" }
+                    button { class: "font-medium text-gray-500 hover:text-gray-700", onclick: move |_| open.set(false), "Close" }
+                }
+            }
+            textarea {
+                class: "w-full h-48 border border-gray-200 rounded-md p-2 text-xs font-mono mb-2",
+                value: "{draft}",
+                oninput: move |evt| draft.set(evt.value.clone()),
+            }
+            DiffPreview { before: before, after: &after }
+        }
+    }
+}
+
+#[inline_props]
+fn ManifestDiffView<'a>(cx: Scope<'a>, diff: &'a ManifestDiff) -> Element {
+    if diff.is_empty() {
+        return render! {
+            div { class: "text-xs text-gray-500 mt-2", "No feature or MSRV changes since {diff.baseline}" }
+        };
+    }
+
+    render! {
+        div { class: "text-xs mt-2 font-mono",
+            div { class: "text-gray-500 mb-1", "Manifest changes since {diff.baseline}" }
+            diff.changes().into_iter().map(|(breaking, change)| {
+                let color = if breaking { "text-red-600" } else { "text-green-600" };
+                rsx! { div { class: "{color}", "{change}" } }
+            })
+        }
+    }
 }
 
 #[inline_props]
@@ -179,33 +664,165 @@ fn CrateDeps<'a>(
     meta: &'a Metadata,
     id: PackageId,
 ) -> Element {
-    let deps = use_state(cx, || collect_package_versions_from_manifest(id, graph));
+    let deps = collect_package_versions_from_manifest(id, graph);
+    let external = collect_external_deps(graph.get_crate(id), meta);
 
     render! {
         ul { class: "text-xs text-gray-500 text-right",
 
             deps.iter().map(|(name, left, right)| {
-                let mut color = "text-green-500";
+                let status = DepStatus::classify(left, right);
+                let color = status.color();
+                let reason = status.reason(name, left, right);
 
-                // If the version is different, then it's not published and invalid
-                if !right.matches(&left) {
-                    color = "text-yellow-500";
+                rsx! {
+                    li { title: "{reason}", "{name} " span { class: "{color} pl-2", "({right})" } }
                 }
+            })
 
-                // If it's a local dep, then it's not published and invalid
-                if right.to_string() == "*" {
-                    color = "text-red-500";
-                }
+            if !external.is_empty() {
+                rsx! { li { class: "border-t border-gray-200 mt-1 pt-1", "crates.io" } }
+            }
 
-                rsx! {
-                    li { "{name} " span { class: "{color} pl-2", "({right})" } }
-                }
+            external.into_iter().map(|(name, req, resolved)| rsx! {
+                ExternalDep { key: "{name}", name: name, req: req, resolved: resolved }
             })
         }
     }
 }
 
-fn collect_package_versions_from_manifest(
+#[inline_props]
+fn ExternalDep(cx: Scope, name: String, req: VersionReq, resolved: Option<Version>) -> Element {
+    // The index is cached across rows, so only the first row asking for a crate waits on the network
+    let entry = use_future(cx, (), |_| {
+        let name = name.clone();
+        async move { crate::registry::index_entry(&name).await.ok() }
+    });
+
+    let (color, status) = match entry.value() {
+        None => ("text-gray-400", "checking...".to_string()),
+        Some(None) => ("text-gray-400", "not in index".to_string()),
+        Some(Some(entry)) => match (resolved, entry.latest()) {
+            (Some(resolved), _) if entry.is_yanked(resolved) => {
+                ("text-red-500", format!("{resolved} is yanked"))
+            }
+            (_, Some(latest)) if !req.matches(latest) => {
+                ("text-yellow-500", format!("outdated, latest is {latest}"))
+            }
+            (_, Some(latest)) => ("text-green-500", format!("latest is {latest}")),
+            (_, None) => ("text-gray-400", "no stable release".to_string()),
+        },
+    };
+
+    render! {
+        li { title: "{status}", "{name} " span { class: "{color} pl-2", "({req})" } }
+    }
+}
+
+/// Third-party crates.io dependencies of a package along with the version cargo resolved for them
+pub fn collect_external_deps(
+    package: &Package,
+    meta: &Metadata,
+) -> Vec<(String, VersionReq, Option<Version>)> {
+    let mut out = package
+        .dependencies
+        .iter()
+        .filter(|dep| matches!(dep.kind, DependencyKind::Normal | DependencyKind::Build))
+        .filter(|dep| dep.source.as_deref() == Some(crate::registry::CRATES_IO))
+        .map(|dep| {
+            let resolved = meta
+                .packages
+                .iter()
+                .filter(|p| p.name == dep.name && dep.req.matches(&p.version))
+                .map(|p| p.version.clone())
+                .max();
+
+            (dep.name.clone(), dep.req.clone(), resolved)
+        })
+        .collect::<Vec<_>>();
+
+    // The same crate can show up more than once when it's a target-specific or build dependency too
+    out.sort_by(|a, b| a.0.cmp(&b.0));
+    out.dedup_by(|a, b| a.0 == b.0);
+
+    out
+}
+
+/// How a workspace dependency's requirement relates to that dependency's current version
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DepStatus {
+    /// The requirement starts at the current version
+    Current,
+
+    /// The requirement is ahead of the current version, so the dependency needs its bump released first
+    PendingBump,
+
+    /// The requirement still allows an older version that might lack the APIs we use now
+    Stale,
+
+    /// There's no version requirement at all, only a path
+    PathOnly,
+}
+
+impl DepStatus {
+    pub fn classify(version: &Version, req: &VersionReq) -> Self {
+        if req.comparators.is_empty() {
+            return Self::PathOnly;
+        }
+
+        match req_minimum(req) {
+            Some(min) if !req.matches(version) && min > *version => Self::PendingBump,
+            _ if !req.matches(version) => Self::Stale,
+            Some(min) if min < *version => Self::Stale,
+            _ => Self::Current,
+        }
+    }
+
+    pub fn color(&self) -> &'static str {
+        match self {
+            DepStatus::Current => "text-green-500",
+            DepStatus::PendingBump => "text-blue-500",
+            DepStatus::Stale => "text-yellow-500",
+            DepStatus::PathOnly => "text-red-500",
+        }
+    }
+
+    pub fn reason(&self, name: &str, version: &Version, req: &VersionReq) -> String {
+        match self {
+            DepStatus::Current => format!("{req} matches {name} {version}"),
+            DepStatus::PendingBump => {
+                format!(
+                    "{req} can only be satisfied once {name} is bumped past {version} and released"
+                )
+            }
+            DepStatus::Stale => {
+                format!(
+                    "{req} allows versions of {name} older than {version} that may lack new APIs"
+                )
+            }
+            DepStatus::PathOnly => {
+                format!("{name} is a path-only dependency and can't be published")
+            }
+        }
+    }
+}
+
+/// The lowest version a requirement accepts, ignoring upper bounds
+pub fn req_minimum(req: &VersionReq) -> Option<Version> {
+    req.comparators
+        .iter()
+        .filter(|c| !matches!(c.op, Op::Less | Op::LessEq))
+        .map(|c| Version {
+            major: c.major,
+            minor: c.minor.unwrap_or(0),
+            patch: c.patch.unwrap_or(0),
+            pre: c.pre.clone(),
+            build: BuildMetadata::EMPTY,
+        })
+        .max()
+}
+
+pub fn collect_package_versions_from_manifest(
     id: &PackageId,
     graph: &CrateGraph,
 ) -> Vec<(String, Version, VersionReq)> {
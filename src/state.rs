@@ -1,32 +1,116 @@
-use cargo_metadata::{DependencyKind, Metadata, PackageId};
-use clap::Parser;
+use crate::cargo_util::{read_workspace, registry_index};
+use crate::config::WorkspaceConfig;
+use crate::git::{self, WorkingTreeStatus};
+use crate::registry::{registry_entry, IndexEntry, SPARSE_INDEX};
+use crate::rows::{
+    collect_package_versions_from_manifest, missing_metadata, release_blockers, DepStatus,
+};
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    semver::{Prerelease, Version},
+    DependencyKind, Metadata, Package, PackageId,
+};
 use dioxus::prelude::*;
 use fermi::Atom;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap, HashSet},
     path::PathBuf,
+    sync::Arc,
 };
+use toml_edit::{Document, Item};
+
+/// The files a release commit picks up: manifests, lockfiles and changelogs anywhere in the workspace
+pub(crate) const RELEASE_PATHSPECS: &[&str] = &[
+    ":(glob)**/Cargo.toml",
+    ":(glob)**/Cargo.lock",
+    ":(glob)**/CHANGELOG.md",
+];
 
 pub static ALLOW_DIRTY: Atom<bool> = |_| false;
 pub static DRY_RUN: Atom<bool> = |_| true;
+pub static PRE_RELEASE: Atom<String> = |_| String::new();
 
-pub fn collect_workspace_meta() -> Metadata {
-    use cargo_metadata::MetadataCommand;
+/// Moved on by the watcher whenever `git status` for the workspace changes, so rows know to
+/// re-read their own crate's status
+pub static WORKING_TREE: Atom<usize> = |_| 0;
+
+/// Workspace settings from the command line that outlive startup. They shape how the metadata is
+/// reloaded and how crates get published
+#[derive(Clone, Default, Debug)]
+pub struct WorkspaceOptions {
+    pub manifest_path: Option<PathBuf>,
+
+    /// Only release these crates
+    pub packages: Vec<String>,
+
+    /// Leave these crates out entirely
+    pub exclude: Vec<String>,
+
+    /// Features to activate when resolving and publishing the workspace
+    pub features: Vec<String>,
+
+    /// Extra cargo config, passed through to every cargo invocation as `--config`
+    pub config: Option<PathBuf>,
+
+    /// Registry to publish to instead of crates.io
+    pub registry: Option<String>,
+
+    pub offline: bool,
+
+    /// Ask cargo to resolve the whole dependency tree, rather than just reading the member
+    /// manifests. Only needed for the resolved versions of third-party dependencies
+    pub resolve: bool,
+}
+
+impl WorkspaceOptions {
+    /// Flags that every cargo command we run should get, from `cargo metadata` to `cargo publish`
+    pub fn cargo_args(&self) -> Vec<String> {
+        let mut args = vec![];
+
+        if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+
+        if let Some(config) = &self.config {
+            args.push("--config".to_string());
+            args.push(config.display().to_string());
+        }
+
+        if self.offline {
+            args.push("--offline".to_string());
+        }
+
+        args
+    }
+}
+
+/// Load the workspace metadata, reading the manifests directly unless the resolver is needed
+pub fn collect_workspace_meta(options: &WorkspaceOptions) -> anyhow::Result<Metadata> {
+    let fast = match options.resolve {
+        true => None,
+        false => read_workspace(options.manifest_path.as_deref()).ok(),
+    };
 
-    #[derive(clap::Parser)]
-    struct Args {
-        path: Option<PathBuf>,
+    match fast {
+        Some(meta) => Ok(meta),
+        None => run_cargo_metadata(options),
     }
+}
 
-    let args = Args::try_parse().unwrap();
+fn run_cargo_metadata(options: &WorkspaceOptions) -> anyhow::Result<Metadata> {
+    use cargo_metadata::MetadataCommand;
 
     let mut cmd = MetadataCommand::new();
 
-    if let Some(path) = args.path {
-        cmd.manifest_path(path.join("Cargo.toml"));
+    if let Some(path) = &options.manifest_path {
+        cmd.manifest_path(path);
     };
 
-    cmd.exec().unwrap()
+    cmd.other_options(options.cargo_args());
+
+    Ok(cmd.exec()?)
 }
 
 fn build_crate_graph(graph: &CrateGraph) -> Vec<(PackageId, usize)> {
@@ -66,6 +150,7 @@ fn build_crate_graph(graph: &CrateGraph) -> Vec<(PackageId, usize)> {
     render_graph
 }
 
+#[derive(Clone)]
 pub struct CrateGraph {
     pub meta: Metadata,
 
@@ -75,9 +160,48 @@ pub struct CrateGraph {
     pub ws_deps: HashMap<PackageId, HashSet<PackageId>>,
 
     pub sorted: Vec<(PackageId, usize)>,
+
+    pub options: WorkspaceOptions,
+
+    pub config: WorkspaceConfig,
 }
 
 impl CrateGraph {
+    /// Collect the workspace metadata and build the graph, narrowed down by `--package` and
+    /// `--exclude`
+    pub fn load(options: WorkspaceOptions) -> anyhow::Result<Self> {
+        let meta = collect_workspace_meta(&options)?;
+        let mut graph = Self::new(&meta);
+        graph.config = WorkspaceConfig::load(&meta)?;
+
+        let find = |name: &String| {
+            graph
+                .crates
+                .iter()
+                .find(|id| graph.get_crate(id).name == *name)
+                .cloned()
+                .ok_or_else(|| anyhow::anyhow!("package `{name}` not found in workspace"))
+        };
+
+        let mut keep = match options.packages.is_empty() {
+            true => graph.crates.clone(),
+            false => options
+                .packages
+                .iter()
+                .map(find)
+                .collect::<anyhow::Result<_>>()?,
+        };
+
+        for name in &options.exclude {
+            keep.remove(&find(name)?);
+        }
+
+        let mut graph = graph.subgraph(&keep);
+        graph.options = options;
+
+        Ok(graph)
+    }
+
     pub fn new(meta: &Metadata) -> Self {
         let crates: HashSet<PackageId> = meta.workspace_members.clone().into_iter().collect();
         let crate_names = crates
@@ -123,6 +247,8 @@ impl CrateGraph {
             crates,
             ws_deps: deps,
             sorted: vec![],
+            options: WorkspaceOptions::default(),
+            config: WorkspaceConfig::default(),
         };
 
         graph.sorted = build_crate_graph(&graph);
@@ -134,113 +260,1124 @@ impl CrateGraph {
         self.meta.packages.iter().find(|p| p.id == *id).unwrap()
     }
 
-    pub fn bump_minor(&self, id: &PackageId) {
-        use toml_edit::{Document, Item};
+    /// Crates that opt out of publishing with `publish = false`, or that the workspace config ignores
+    pub fn default_ignored(&self) -> HashSet<PackageId> {
+        self.crates
+            .iter()
+            .filter(|id| {
+                let package = self.get_crate(id);
+                package.publish == Some(vec![]) || self.config.is_ignored(&package.name)
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// The crates in this graph with the same names as `ids` in `other`, for carrying a set of
+    /// crates across a reload that changed their ids
+    pub fn same_crates(&self, other: &CrateGraph, ids: &HashSet<PackageId>) -> HashSet<PackageId> {
+        let names = ids
+            .iter()
+            .map(|id| other.get_crate(id).name.as_str())
+            .collect::<HashSet<_>>();
+
+        self.crates
+            .iter()
+            .filter(|id| names.contains(self.get_crate(id).name.as_str()))
+            .cloned()
+            .collect()
+    }
+
+    /// Write the workspace config back to disk
+    pub fn save_config(&self) -> anyhow::Result<()> {
+        self.config.save(&self.meta.workspace_root)
+    }
+
+    /// The crates that `id` depends on within the workspace, directly or not, including `id` itself
+    pub fn transitive_deps(&self, id: &PackageId) -> HashSet<PackageId> {
+        self.walk(id, |id| self.ws_deps[id].iter().cloned().collect())
+    }
+
+    /// The crates that depend on `id`, directly or not, including `id` itself
+    pub fn transitive_dependents(&self, id: &PackageId) -> HashSet<PackageId> {
+        self.walk(id, |id| {
+            self.ws_deps
+                .iter()
+                .filter(|(_, deps)| deps.contains(id))
+                .map(|(dependent, _)| dependent.clone())
+                .collect()
+        })
+    }
+
+    fn walk(
+        &self,
+        start: &PackageId,
+        next: impl Fn(&PackageId) -> Vec<PackageId>,
+    ) -> HashSet<PackageId> {
+        let mut seen = HashSet::from([start.clone()]);
+        let mut queue = vec![start.clone()];
+
+        while let Some(id) = queue.pop() {
+            for neighbor in next(&id) {
+                if seen.insert(neighbor.clone()) {
+                    queue.push(neighbor);
+                }
+            }
+        }
+
+        seen
+    }
+
+    /// The part of the graph around a single crate
+    pub fn focus(&self, id: &PackageId, focus: Focus) -> Self {
+        let keep = match focus {
+            Focus::Dependencies => self.transitive_deps(id),
+            Focus::Dependents => self.transitive_dependents(id),
+            Focus::Both => &self.transitive_deps(id) | &self.transitive_dependents(id),
+        };
+
+        self.subgraph(&keep)
+    }
+
+    /// A graph with only the given crates, and only the edges between them
+    pub fn subgraph(&self, keep: &HashSet<PackageId>) -> Self {
+        let ws_deps = self
+            .ws_deps
+            .iter()
+            .filter(|(id, _)| keep.contains(id))
+            .map(|(id, deps)| (id.clone(), deps.intersection(keep).cloned().collect()))
+            .collect();
+
+        let mut graph = Self {
+            meta: self.meta.clone(),
+            crates: self.crates.intersection(keep).cloned().collect(),
+            ws_deps,
+            sorted: vec![],
+            options: self.options.clone(),
+            config: self.config.clone(),
+        };
+
+        graph.sorted = build_crate_graph(&graph);
+
+        graph
+    }
+
+    /// The order to publish crates in: every crate comes after all of its workspace dependencies
+    ///
+    /// Ties are broken by `sorted`, so the order stays close to the one shown in the rows.
+    pub fn release_order(&self) -> Vec<PackageId> {
+        let mut order: Vec<PackageId> = vec![];
+        let mut remaining = self.sorted.iter().map(|(id, _)| id).collect::<Vec<_>>();
+
+        while !remaining.is_empty() {
+            let next = remaining
+                .iter()
+                .position(|id| {
+                    self.ws_deps[*id]
+                        .iter()
+                        .all(|dep| dep == *id || order.contains(dep))
+                })
+                // A dependency cycle can't be ordered, so just take the rest as they come
+                .unwrap_or(0);
+
+            order.push(remaining.remove(next).clone());
+        }
+
+        order
+    }
+
+    /// Grow a selection of crates into everything that has to be released alongside them
+    ///
+    /// A workspace dependency joins the plan when no published version satisfies the requirement
+    /// on it, which is checked against the index. If the index can't be reached we assume the
+    /// dependency needs releasing too.
+    pub async fn release_closure(&self, selected: &HashSet<PackageId>) -> HashSet<PackageId> {
+        let mut plan = selected.clone();
+        let mut queue = selected.iter().cloned().collect::<Vec<_>>();
+
+        while let Some(id) = queue.pop() {
+            for (name, _, req) in collect_package_versions_from_manifest(&id, self) {
+                let dep = self.ws_deps[&id]
+                    .iter()
+                    .find(|dep| self.get_crate(dep).name == name)
+                    .unwrap();
+
+                if plan.contains(dep) {
+                    continue;
+                }
+
+                let satisfied = match self.index_entry(&name).await {
+                    Ok(entry) => entry
+                        .versions
+                        .iter()
+                        .any(|v| !v.yanked && req.matches(&v.version)),
+                    Err(_) => false,
+                };
+
+                if !satisfied {
+                    plan.insert(dep.clone());
+                    queue.push(dep.clone());
+                }
+            }
+        }
+
+        plan
+    }
+
+    /// Pairs of (crate, ignored workspace dependency) for crates in the plan, which will be
+    /// published against whatever version of the ignored crate is already on the registry
+    pub fn ignored_dependencies(
+        &self,
+        plan: &[PackageId],
+        ignored: &HashSet<PackageId>,
+    ) -> Vec<(PackageId, PackageId)> {
+        plan.iter()
+            .flat_map(|id| {
+                self.ws_deps[id]
+                    .iter()
+                    .filter(|dep| ignored.contains(*dep))
+                    .map(move |dep| (id.clone(), dep.clone()))
+            })
+            .collect()
+    }
+
+    /// Where a crate stands in the release, given the ignored crates, the ones whose current
+    /// version is already on crates.io, what changed since each crate's last release tag, and the
+    /// ones with an unreleased bump
+    pub fn release_status(
+        &self,
+        id: &PackageId,
+        ignored: &HashSet<PackageId>,
+        published: &HashSet<PackageId>,
+        changes: &HashMap<PackageId, ReleaseChanges>,
+        bumped: &HashSet<PackageId>,
+    ) -> ReleaseStatus {
+        if ignored.contains(id) {
+            return ReleaseStatus::Ignored;
+        }
+
+        let needs_release = changes.get(id).map(ReleaseChanges::needs_release);
+        if needs_release == Some(false) {
+            return ReleaseStatus::Unchanged;
+        }
+
+        if published.contains(id) {
+            // Commits of its own since the release, or releasing a dependency, mean this crate
+            // has to follow with a new version
+            let deps_pending = self.ws_deps[id]
+                .iter()
+                .any(|dep| !published.contains(dep) && !ignored.contains(dep));
+
+            return match deps_pending || needs_release == Some(true) {
+                true => ReleaseStatus::NeedsBump,
+                false => ReleaseStatus::Published,
+            };
+        }
 
         let package = self.get_crate(id);
+        let blocked = !release_blockers(package, &self.config, bumped.contains(id)).is_empty()
+            || !missing_metadata(package, &self.config).is_empty();
+
+        match blocked {
+            true => ReleaseStatus::Blocked,
+            false => ReleaseStatus::Ready,
+        }
+    }
+
+    pub fn release_statuses(
+        &self,
+        ignored: &HashSet<PackageId>,
+        published: &HashSet<PackageId>,
+        changes: &HashMap<PackageId, ReleaseChanges>,
+        bumped: &HashSet<PackageId>,
+    ) -> HashMap<PackageId, ReleaseStatus> {
+        self.crates
+            .iter()
+            .map(|id| {
+                let status = self.release_status(id, ignored, published, changes, bumped);
+                (id.clone(), status)
+            })
+            .collect()
+    }
+
+    /// Rebuild the graph from the same workspace's manifests
+    ///
+    /// This always skips cargo's resolver, which is slow enough to hold up the window. See
+    /// [`resolve_dependencies`](crate::watch::resolve_dependencies) for filling it in afterwards.
+    pub fn reload(&self) -> anyhow::Result<Self> {
+        let mut options = self.options.clone();
+        options.manifest_path = Some(self.meta.workspace_root.join("Cargo.toml").into());
+        options.resolve = false;
 
-        let mut version = package.version.clone();
-        version.minor += 1;
+        Self::load(options)
+    }
 
-        println!("Bumping {} to {}", package.name, version);
+    /// A crate's directory relative to the workspace root, and the other members nested inside it
+    pub fn crate_paths(&self, id: &PackageId) -> (Utf8PathBuf, Vec<Utf8PathBuf>) {
+        let root = &self.meta.workspace_root;
+        let crate_dir = |id: &PackageId| {
+            let dir = self.get_crate(id).manifest_path.parent().unwrap_or(root);
+            dir.strip_prefix(root).unwrap_or(dir).to_path_buf()
+        };
 
-        let contents = std::fs::read_to_string(&package.manifest_path).unwrap();
+        let dir = crate_dir(id);
+        let nested = self
+            .crates
+            .iter()
+            .filter(|other| *other != id)
+            .map(crate_dir)
+            .filter(|other| other.starts_with(&dir) && *other != dir)
+            .collect();
 
-        let mut doc = contents.parse::<Document>().expect("invalid doc");
+        (dir, nested)
+    }
 
-        doc["package"]["version"] = Item::Value(version.to_string().into());
+    /// Uncommitted changes in a crate's directory, not counting workspace members nested inside it
+    pub async fn working_tree_status(&self, id: &PackageId) -> anyhow::Result<WorkingTreeStatus> {
+        let (dir, nested) = self.crate_paths(id);
+        git::status(&self.meta.workspace_root, &dir, &nested).await
+    }
+
+    /// The tag of the newest release of a crate, going by the workspace's tag pattern
+    pub async fn last_release_tag(
+        &self,
+        id: &PackageId,
+    ) -> anyhow::Result<Option<(String, Version)>> {
+        let name = &self.get_crate(id).name;
 
-        // write back to fs
-        std::fs::write(&package.manifest_path, doc.to_string()).unwrap();
+        Ok(git::tags(&self.meta.workspace_root)
+            .await?
+            .into_iter()
+            .filter_map(|tag| {
+                let version = self.config.parse_release_tag(name, &tag)?;
+                Some((tag, version))
+            })
+            .max_by(|(_, a), (_, b)| a.cmp(b)))
+    }
+
+    /// How much a crate has changed since its last release tag, or None if it's never been tagged
+    ///
+    /// Only commits inside the crate's own directory count, so a crate whose workspace
+    /// dependencies moved on without it doesn't need a release of its own.
+    pub async fn changes_since_release(
+        &self,
+        id: &PackageId,
+    ) -> anyhow::Result<Option<ReleaseChanges>> {
+        let Some((tag, version)) = self.last_release_tag(id).await? else {
+            return Ok(None);
+        };
+
+        let (dir, nested) = self.crate_paths(id);
+        let commits = git::commit_count(&self.meta.workspace_root, &tag, &dir, &nested).await?;
+
+        Ok(Some(ReleaseChanges {
+            tag,
+            version,
+            commits,
+        }))
+    }
 
-        for dep in self.ws_deps[id].iter() {
-            let dep = self.get_crate(dep);
-            doc["dependencies"][&dep.name]["version"] =
-                Item::Value(format!("^{}", version.to_string()).into());
-            std::fs::write(&dep.manifest_path, doc.to_string()).unwrap();
+    /// Whether a crate's version is ahead of both its last release tag and the newest version on
+    /// the index, meaning it's been bumped for a release that hasn't happened yet
+    ///
+    /// A crate that's never been tagged or published has nothing to be ahead of, so it isn't.
+    pub async fn has_unreleased_bump(&self, id: &PackageId) -> bool {
+        let package = self.get_crate(id);
+        let tagged = self.last_release_tag(id).await.ok().flatten();
+        let published = self
+            .index_entry(&package.name)
+            .await
+            .ok()
+            .and_then(|entry| entry.versions.iter().map(|v| v.version.clone()).max());
+
+        match tagged.map(|(_, version)| version).max(published) {
+            Some(baseline) => package.version > baseline,
+            None => false,
+        }
+    }
+
+    /// The sparse index of the registry crates are published to: crates.io's, or the `--registry`
+    /// as configured for cargo
+    ///
+    /// A git index can't be read over HTTP, so that's an error rather than falling back to
+    /// whatever crate has the same name on crates.io.
+    pub fn publish_index(&self) -> anyhow::Result<String> {
+        let Some(registry) = &self.options.registry else {
+            return Ok(SPARSE_INDEX.to_string());
+        };
+
+        let index = registry_index(self.meta.workspace_root.as_std_path(), registry)?;
+        match index.strip_prefix("sparse+") {
+            Some(url) => Ok(url.trim_end_matches('/').to_string()),
+            None => {
+                anyhow::bail!("can't read the index of `{registry}` ({index}), it isn't sparse")
+            }
         }
     }
+
+    /// A workspace crate's entry in the index it's published to
+    pub async fn index_entry(&self, name: &str) -> anyhow::Result<Arc<IndexEntry>> {
+        registry_entry(&self.publish_index()?, name).await
+    }
+
+    /// Whether the index already has the crate's current version, so publishing it would fail
+    pub async fn is_published(&self, id: &PackageId) -> bool {
+        let package = self.get_crate(id);
+
+        self.index_entry(&package.name)
+            .await
+            .map(|entry| entry.is_published(&package.version))
+            .unwrap_or(false)
+    }
+
+    /// Whether a crate's current version has already gone out, either tagged or on the index
+    pub async fn is_released(&self, id: &PackageId) -> bool {
+        let package = self.get_crate(id);
+        let tag = self.config.release_tag(&package.name, &package.version);
+
+        let tagged = git::tags(&self.meta.workspace_root)
+            .await
+            .map(|tags| tags.contains(&tag))
+            .unwrap_or(false);
+
+        tagged || self.is_published(id).await
+    }
+
+    /// The crates whose current version is already on the index
+    pub async fn published_crates(&self) -> HashSet<PackageId> {
+        let mut published = HashSet::new();
+
+        for id in &self.crates {
+            if self.is_published(id).await {
+                published.insert(id.clone());
+            }
+        }
+
+        published
+    }
+
+    /// The crates that have an unreleased bump, see [`CrateGraph::has_unreleased_bump`]
+    pub async fn bumped_crates(&self) -> HashSet<PackageId> {
+        let mut bumped = HashSet::new();
+
+        for id in &self.crates {
+            if self.has_unreleased_bump(id).await {
+                bumped.insert(id.clone());
+            }
+        }
+
+        bumped
+    }
+
+    /// Commit the manifest changes and tag the releases, as far as the workspace config asks to,
+    /// once a run of crates has been published
+    ///
+    /// The whole run goes in one commit listing every crate, since a bump's edits to manifests
+    /// and the lockfile can't be pulled apart per crate. Crates that share a version can share a
+    /// tag too (a `v{version}` pattern, or lockstep), so each tag is only made once.
+    pub async fn record_release(&self, released: &[PackageId]) -> anyhow::Result<()> {
+        let root = &self.meta.workspace_root;
+        let packages = released
+            .iter()
+            .map(|id| self.get_crate(id))
+            .collect::<Vec<_>>();
+
+        if self.config.commit && !packages.is_empty() {
+            let versions = packages
+                .iter()
+                .map(|package| (package.name.as_str(), &package.version))
+                .collect::<Vec<_>>();
+            let message = self.config.release_commit_message(&versions);
+            git::commit(root, &message, RELEASE_PATHSPECS).await?;
+        }
+
+        if self.config.tag {
+            let mut tagged = HashSet::new();
+
+            for package in packages {
+                let tag = self.config.release_tag(&package.name, &package.version);
+
+                if tagged.insert(tag.clone()) {
+                    let message = format!("{} v{}", package.name, package.version);
+                    git::tag(root, &tag, &message).await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Release tags for the current version of each crate that have been created locally
+    pub async fn release_tags(&self, ignored: &HashSet<PackageId>) -> anyhow::Result<Vec<String>> {
+        let existing = git::tags(&self.meta.workspace_root)
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+
+        Ok(self
+            .release_order()
+            .iter()
+            .filter(|id| !ignored.contains(id))
+            .map(|id| {
+                let package = self.get_crate(id);
+                self.config.release_tag(&package.name, &package.version)
+            })
+            .filter(|tag| existing.contains(tag))
+            .collect())
+    }
+
+    /// Push the current release tags to the configured remote, returning the ones pushed
+    pub async fn push_release_tags(
+        &self,
+        ignored: &HashSet<PackageId>,
+    ) -> anyhow::Result<Vec<String>> {
+        let tags = self.release_tags(ignored).await?;
+
+        if !tags.is_empty() {
+            git::push_tags(&self.meta.workspace_root, &self.config.remote, &tags).await?;
+        }
+
+        Ok(tags)
+    }
+
+    /// How much each crate has changed since its last release tag, for the crates that have one
+    pub async fn release_changes(&self) -> HashMap<PackageId, ReleaseChanges> {
+        let mut changes = HashMap::new();
+
+        for id in &self.crates {
+            if let Ok(Some(since)) = self.changes_since_release(id).await {
+                changes.insert(id.clone(), since);
+            }
+        }
+
+        changes
+    }
+
+    /// Every crate in the workspace, undoing `--package` and `--exclude`, which only narrow what's
+    /// shown and published. Version edits go through this graph so that crates left out of the
+    /// view still get their requirements updated
+    fn workspace_graph(&self) -> Option<Self> {
+        if self.crates.len() == self.meta.workspace_members.len() {
+            return None;
+        }
+
+        let mut graph = Self::new(&self.meta);
+        graph.options = self.options.clone();
+        graph.config = self.config.clone();
+
+        Some(graph)
+    }
+
+    /// Rewrite every stale intra-workspace requirement to `^<current version>`
+    ///
+    /// Nothing is written to disk: the edits are returned so they can be previewed and applied later.
+    pub fn sync_requirements(&self) -> anyhow::Result<Vec<ManifestEdit>> {
+        if let Some(workspace) = self.workspace_graph() {
+            return workspace.sync_requirements();
+        }
+
+        let mut docs = ManifestDocs::new();
+
+        for (id, _) in &self.sorted {
+            let package = self.get_crate(id);
+
+            for (name, version, req) in collect_package_versions_from_manifest(id, self) {
+                if DepStatus::classify(&version, &req) != DepStatus::Stale {
+                    continue;
+                }
+
+                self.set_workspace_requirement(&mut docs, package, &name, &format!("^{version}"))?;
+            }
+        }
+
+        Ok(into_edits(docs))
+    }
+
+    /// Whether bumping `id` moves the whole workspace, `[workspace.package].version` included
+    fn bumps_in_lockstep(&self, id: &PackageId) -> bool {
+        self.config.lockstep && !self.default_ignored().contains(id)
+    }
+
+    /// The crates that move together with `id` when it's bumped: every crate that isn't ignored
+    /// in lockstep mode, the rest of its group for lockstep groups, otherwise just `id`
+    pub fn bump_targets(&self, id: &PackageId) -> Vec<PackageId> {
+        if let Some(workspace) = self.workspace_graph() {
+            return workspace.bump_targets(id);
+        }
+
+        let ignored = self.default_ignored();
+        if ignored.contains(id) {
+            return vec![id.clone()];
+        }
+
+        let group_of = |id: &PackageId| self.config.group_of(&self.get_crate(id).name);
+        let group = match group_of(id) {
+            Some((name, group)) if group.lockstep => Some(name),
+            _ => None,
+        };
+
+        if !self.config.lockstep && group.is_none() {
+            return vec![id.clone()];
+        }
+
+        self.crates
+            .iter()
+            .filter(|id| !ignored.contains(id))
+            .filter(|id| self.config.lockstep || group_of(id).map(|(name, _)| name) == group)
+            .cloned()
+            .collect()
+    }
+
+    /// The version a bump of `id` lands on. Crates bumped together start from the highest of
+    /// their versions, and `pre` sets a pre-release label like `alpha.1`
+    pub fn next_version(
+        &self,
+        id: &PackageId,
+        level: BumpLevel,
+        pre: Option<&str>,
+    ) -> anyhow::Result<Version> {
+        let current = self
+            .bump_targets(id)
+            .iter()
+            .map(|id| &self.get_crate(id).version)
+            .max()
+            .unwrap_or(&self.get_crate(id).version);
+
+        if level == BumpLevel::Pre && current.pre.is_empty() {
+            anyhow::bail!("{current} isn't a pre-release, start one with a label on a bigger bump");
+        }
+
+        let mut version = level.apply(current);
+        if let Some(pre) = pre {
+            version.pre = Prerelease::new(pre)?;
+        }
+
+        Ok(version)
+    }
+
+    /// Compute the manifest edits for bumping a crate, including the requirement in every crate
+    /// that depends on it
+    ///
+    /// In lockstep mode every crate that isn't ignored gets the new version, along with
+    /// `[workspace.package].version` for the crates that inherit it, which is refused if an
+    /// ignored crate inherits it too. Lockstep groups move all of their members instead.
+    pub fn plan_bump(
+        &self,
+        id: &PackageId,
+        level: BumpLevel,
+        pre: Option<&str>,
+    ) -> anyhow::Result<Vec<ManifestEdit>> {
+        if let Some(workspace) = self.workspace_graph() {
+            return workspace.plan_bump(id, level, pre);
+        }
+
+        let version = self.next_version(id, level, pre)?;
+        let targets = self.bump_targets(id);
+        let lockstep = self.bumps_in_lockstep(id);
+        let mut docs = ManifestDocs::new();
+
+        if lockstep {
+            let root_manifest = self.meta.workspace_root.join("Cargo.toml");
+            let root = open_manifest(&mut docs, &root_manifest)?;
+            let ws_version = root
+                .get_mut("workspace")
+                .and_then(|workspace| workspace.get_mut("package"))
+                .and_then(|ws_package| ws_package.get_mut("version"));
+
+            if let Some(ws_version) = ws_version {
+                *ws_version = toml_edit::value(version.to_string());
+                self.check_ignored_inherit_version(&mut docs)?;
+            }
+        }
+
+        for id in &targets {
+            let package = self.get_crate(id);
+            let doc = open_manifest(&mut docs, &package.manifest_path)?;
+
+            if doc["package"]["version"].is_table_like() {
+                if !lockstep {
+                    anyhow::bail!("{} inherits its version from the workspace", package.name);
+                }
+            } else {
+                doc["package"]["version"] = toml_edit::value(version.to_string());
+            }
+
+            // Point every crate that depends on this one at the new version
+            for (dependent, deps) in &self.ws_deps {
+                if deps.contains(id) {
+                    let dependent = self.get_crate(dependent);
+                    let new_req = format!("^{version}");
+                    self.set_workspace_requirement(&mut docs, dependent, &package.name, &new_req)?;
+                }
+            }
+        }
+
+        Ok(into_edits(docs))
+    }
+
+    /// Refuse a lockstep bump that would drag ignored crates along through the
+    /// `[workspace.package].version` they inherit
+    fn check_ignored_inherit_version(&self, docs: &mut ManifestDocs) -> anyhow::Result<()> {
+        let mut inheriting = vec![];
+
+        for id in self.default_ignored() {
+            let package = self.get_crate(&id);
+            let doc = open_manifest(docs, &package.manifest_path)?;
+
+            if doc["package"]["version"].is_table_like() {
+                inheriting.push(package.name.as_str());
+            }
+        }
+
+        if !inheriting.is_empty() {
+            inheriting.sort();
+            anyhow::bail!(
+                "ignored crates inherit [workspace.package].version and would be bumped too: {}. \
+                 Give them a version of their own or stop ignoring them",
+                inheriting.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn bump(&self, id: &PackageId, level: BumpLevel, pre: Option<&str>) -> anyhow::Result<()> {
+        let package = self.get_crate(id);
+        let version = self.next_version(id, level, pre)?;
+        println!("Bumping {} to {version}", package.name);
+
+        for edit in self.plan_bump(id, level, pre)? {
+            edit.apply()?;
+        }
+
+        Ok(())
+    }
+
+    /// Set the requirement `dependent` has on the workspace crate `dep_name`
+    ///
+    /// Requirements inherited with `workspace = true` are rewritten in the root manifest instead.
+    fn set_workspace_requirement(
+        &self,
+        docs: &mut ManifestDocs,
+        dependent: &Package,
+        dep_name: &str,
+        new_req: &str,
+    ) -> anyhow::Result<()> {
+        let dep = dependent
+            .dependencies
+            .iter()
+            .find(|dep| dep.name == dep_name && dep.kind == DependencyKind::Normal)
+            .unwrap();
+
+        let key = dep.rename.as_deref().unwrap_or(dep_name);
+
+        let doc = open_manifest(docs, &dependent.manifest_path)?;
+        let table = match &dep.target {
+            Some(target) => &mut doc["target"][&target.to_string()]["dependencies"],
+            None => &mut doc["dependencies"],
+        };
+
+        let entry = &mut table[key];
+
+        let inherited = entry
+            .get("workspace")
+            .and_then(|item| item.as_bool())
+            .unwrap_or(false);
+
+        if inherited {
+            let root = open_manifest(docs, &self.meta.workspace_root.join("Cargo.toml"))?;
+            set_dependency_req(&mut root["workspace"]["dependencies"][dep_name], new_req);
+        } else {
+            set_dependency_req(entry, new_req);
+        }
+
+        Ok(())
+    }
 }
 
-fn write_crate_graph(
-    deps: &HashMap<PackageId, HashSet<PackageId>>,
-    meta: &Metadata,
-    render_graph: &Vec<(PackageId, usize)>,
-) {
-    // add the workspace members to the graph
-    let mut dag = VisualGraph::new(Orientation::TopToBottom);
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReleaseStatus {
+    /// Unpublished version with nothing standing in its way
+    Ready,
 
-    // Define the node styles:
-    let look0 = StyleAttr::simple();
-    let sz = Point::new(100., 100.);
+    /// Fails a release check, or is missing a field crates.io would reject it for
+    Blocked,
 
-    let mut mapping = HashMap::new();
+    /// Already published, but there are commits since its release tag, or a workspace dependency
+    /// has an unpublished version it should pick up
+    NeedsBump,
 
-    for (package, _) in render_graph.iter().rev() {
-        let package_ = &meta.packages.iter().find(|p| p.id == *package).unwrap();
+    /// This exact version is already on crates.io
+    Published,
 
-        let package_name = package_.name.as_str();
-        let package_version = package_.version.to_string();
+    /// No commits touch the crate since its last release tag
+    Unchanged,
 
-        if deps.contains_key(&package) && package_.publish == Some(vec![]) {
-            continue;
+    /// Left out of the release entirely
+    Ignored,
+}
+
+impl ReleaseStatus {
+    pub const ALL: [ReleaseStatus; 6] = [
+        ReleaseStatus::Ready,
+        ReleaseStatus::Blocked,
+        ReleaseStatus::NeedsBump,
+        ReleaseStatus::Published,
+        ReleaseStatus::Unchanged,
+        ReleaseStatus::Ignored,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ReleaseStatus::Ready => "ready",
+            ReleaseStatus::Blocked => "blocked by checks",
+            ReleaseStatus::NeedsBump => "needs bump",
+            ReleaseStatus::Published => "already published",
+            ReleaseStatus::Unchanged => "no release needed",
+            ReleaseStatus::Ignored => "ignored",
         }
+    }
 
-        let id = dag.add_node(Element::create(
-            ShapeKind::new_box(&format!("{package_name}\nv{package_version}")),
-            look0.clone(),
-            Orientation::TopToBottom,
-            sz,
-        ));
-        mapping.insert(package, id);
+    /// Fill colour for the graph node
+    pub fn color(self) -> &'static str {
+        match self {
+            ReleaseStatus::Ready => "#bbf7d0",
+            ReleaseStatus::Blocked => "#fecaca",
+            ReleaseStatus::NeedsBump => "#fef08a",
+            ReleaseStatus::Published => "#bfdbfe",
+            ReleaseStatus::Unchanged => "#ccfbf1",
+            ReleaseStatus::Ignored => "#e5e7eb",
+        }
+    }
+}
+
+/// Where a crate stands against its last release tag
+#[derive(Clone, PartialEq, Debug)]
+pub struct ReleaseChanges {
+    pub tag: String,
+    pub version: Version,
+    pub commits: usize,
+}
+
+impl ReleaseChanges {
+    pub fn needs_release(&self) -> bool {
+        self.commits > 0
+    }
+}
+
+/// Which side of a crate to keep when focusing the graph on it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Focus {
+    Dependencies,
+    Dependents,
+    Both,
+}
+
+/// How far to move a version forward
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, clap::ValueEnum, Serialize, Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum BumpLevel {
+    /// The next pre-release of the same version, like `alpha.1` to `alpha.2`
+    Pre,
+    Patch,
+    Minor,
+    Major,
+}
+
+impl BumpLevel {
+    /// Move `version` forward. A pre-release is promoted to its release by every level but `Pre`,
+    /// so `0.3.0-alpha.1` becomes `0.3.0` rather than skipping past it
+    pub fn apply(self, version: &Version) -> Version {
+        let mut version = version.clone();
+
+        if !version.pre.is_empty() && self != BumpLevel::Pre {
+            version.pre = Prerelease::EMPTY;
+            return version;
+        }
+
+        match self {
+            // A release has no pre-release to move on to, so it's left alone
+            BumpLevel::Pre if version.pre.is_empty() => {}
+            BumpLevel::Pre => version.pre = next_prerelease(&version.pre),
+            BumpLevel::Patch => version.patch += 1,
+            BumpLevel::Minor => {
+                version.minor += 1;
+                version.patch = 0;
+            }
+            BumpLevel::Major => {
+                version.major += 1;
+                version.minor = 0;
+                version.patch = 0;
+            }
+        }
+
+        version
+    }
+
+    /// The smallest bump that signals a change to cargo's resolver
+    ///
+    /// Under 0.x the minor version is the breaking one, and under 0.0.x every patch is breaking.
+    /// Pre-releases promise nothing, so after one the next pre-release is always enough.
+    pub fn for_change(version: &Version, breaking: bool, additive: bool) -> Self {
+        if !version.pre.is_empty() {
+            return BumpLevel::Pre;
+        }
+
+        match (version.major, version.minor) {
+            (0, 0) => BumpLevel::Patch,
+            (0, _) if breaking => BumpLevel::Minor,
+            (0, _) => BumpLevel::Patch,
+            _ if breaking => BumpLevel::Major,
+            _ if additive => BumpLevel::Minor,
+            _ => BumpLevel::Patch,
+        }
+    }
+
+    /// The bump that took `from` to `to`, if `to` is actually ahead
+    pub fn between(from: &Version, to: &Version) -> Option<Self> {
+        if to <= from {
+            return None;
+        }
+
+        Some(if to.major != from.major {
+            BumpLevel::Major
+        } else if to.minor != from.minor {
+            BumpLevel::Minor
+        } else if to.patch != from.patch || to.pre.is_empty() {
+            BumpLevel::Patch
+        } else {
+            BumpLevel::Pre
+        })
     }
 
-    for (package, _) in render_graph {
-        let Some(&id) = mapping.get(package) else { continue;};
+    pub fn label(self) -> &'static str {
+        match self {
+            BumpLevel::Pre => "Pre",
+            BumpLevel::Patch => "Patch",
+            BumpLevel::Minor => "Minor",
+            BumpLevel::Major => "Major",
+        }
+    }
+}
 
-        let deps = deps.get(package).unwrap();
+/// The pre-release after `pre`: its last numeric identifier goes up by one, or `.1` is added when
+/// it doesn't end in a number, so `alpha.1` becomes `alpha.2` and `beta` becomes `beta.1`
+fn next_prerelease(pre: &Prerelease) -> Prerelease {
+    let mut parts = pre
+        .as_str()
+        .split('.')
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    match parts.last().and_then(|last| last.parse::<u64>().ok()) {
+        Some(number) => *parts.last_mut().unwrap() = (number + 1).to_string(),
+        None => parts.push("1".to_string()),
+    }
+
+    // Built from a valid pre-release and a number, so it's valid too
+    Prerelease::new(&parts.join(".")).unwrap()
+}
+
+/// Change the workspace config held by the graph and write it straight back to disk
+pub fn update_config(graph: &UseState<CrateGraph>, update: impl FnOnce(&mut WorkspaceConfig)) {
+    graph.with_mut(|graph| {
+        update(&mut graph.config);
+
+        if let Err(err) = graph.save_config() {
+            println!("Failed to save workspace config: {err}");
+        }
+    });
+}
+
+/// A pending rewrite of a manifest, kept around so it can be previewed before it's applied
+pub struct ManifestEdit {
+    pub manifest_path: Utf8PathBuf,
+    pub before: String,
+    pub after: String,
+}
+
+impl ManifestEdit {
+    pub fn apply(&self) -> anyhow::Result<()> {
+        std::fs::write(&self.manifest_path, &self.after)?;
+        Ok(())
+    }
+}
+
+/// Manifests opened for editing, along with their contents before any edits
+type ManifestDocs = BTreeMap<Utf8PathBuf, (String, Document)>;
+
+fn into_edits(docs: ManifestDocs) -> Vec<ManifestEdit> {
+    docs.into_iter()
+        .map(|(manifest_path, (before, doc))| ManifestEdit {
+            manifest_path,
+            before,
+            after: doc.to_string(),
+        })
+        .filter(|edit| edit.before != edit.after)
+        .collect()
+}
+
+/// Load a manifest into the set of documents being edited, reusing it if it's already open
+fn open_manifest<'a>(
+    docs: &'a mut ManifestDocs,
+    path: &Utf8Path,
+) -> anyhow::Result<&'a mut Document> {
+    if !docs.contains_key(path) {
+        let contents = std::fs::read_to_string(path)?;
+        let doc = contents.parse::<Document>()?;
+        docs.insert(path.to_path_buf(), (contents, doc));
+    }
+
+    Ok(&mut docs.get_mut(path).unwrap().1)
+}
+
+/// Point a dependency entry at a new requirement, whether it's written as a bare string or a table
+fn set_dependency_req(entry: &mut Item, req: &str) {
+    if entry.is_str() {
+        *entry = toml_edit::value(req);
+    } else if let Some(table) = entry.as_table_like_mut() {
+        table.insert("version", toml_edit::value(req));
+    }
+}
+
+/// The crates and edges that make up the graph views, core crates first
+///
+/// Each edge points from a dependency to the crate that depends on it, along with how the
+/// dependent's requirement compares to the dependency's current version.
+fn graph_elements(graph: &CrateGraph) -> (Vec<&Package>, Vec<(&Package, &Package, DepStatus)>) {
+    let nodes = graph
+        .sorted
+        .iter()
+        .rev()
+        .map(|(id, _)| graph.get_crate(id))
+        .collect::<Vec<_>>();
+
+    let mut edges = vec![];
+
+    for (package, _) in &graph.sorted {
+        let package = graph.get_crate(package);
+        let reqs = collect_package_versions_from_manifest(&package.id, graph);
 
         // sort the dependencies by the render graph
-        let mut deps = deps.iter().collect::<Vec<_>>();
-        deps.sort_by(|a, b| {
-            let a = render_graph.iter().position(|(p, _)| p == *a).unwrap();
-            let b = render_graph.iter().position(|(p, _)| p == *b).unwrap();
-            a.cmp(&b)
-        });
+        let mut deps = graph.ws_deps[&package.id].iter().collect::<Vec<_>>();
+        deps.sort_by_key(|dep| graph.sorted.iter().position(|(p, _)| p == *dep).unwrap());
 
         for dep in deps {
-            let Some(&dep_id) = mapping.get(dep) else { continue ;};
-
-            if id == dep_id {
+            let dep = graph.get_crate(dep);
+            if dep.id == package.id {
                 continue;
             }
 
-            let package_version = meta
-                .packages
-                .iter()
-                .find(|p| p.id == *dep)
-                .unwrap()
-                .version
-                .to_string();
-
-            // Add an edge between the nodes.
-            let arrow = Arrow::simple(&package_version);
-            dag.add_edge(arrow, dep_id, id);
+            let (_, version, req) = reqs.iter().find(|(name, _, _)| *name == dep.name).unwrap();
+            edges.push((dep, package, DepStatus::classify(version, req)));
         }
     }
 
+    (nodes, edges)
+}
+
+/// Colour for an edge whose requirement needs attention
+fn edge_color(status: DepStatus) -> Option<&'static str> {
+    match status {
+        DepStatus::Current => None,
+        DepStatus::PendingBump => Some("#3b82f6"),
+        DepStatus::Stale => Some("#eab308"),
+        DepStatus::PathOnly => Some("#ef4444"),
+    }
+}
+
+/// Lay out the workspace's dependency graph and render it to an SVG document, colouring each crate
+/// by its release status
+pub fn write_crate_graph(
+    graph: &CrateGraph,
+    statuses: &HashMap<PackageId, ReleaseStatus>,
+) -> String {
     use layout::backends::svg::SVGWriter;
     use layout::core::base::Orientation;
+    use layout::core::color::Color;
     use layout::core::geometry::Point;
     use layout::core::style::*;
-    use layout::core::utils::save_to_file;
     use layout::std_shapes::shapes::*;
     use layout::topo::layout::VisualGraph;
 
+    let (nodes, edges) = graph_elements(graph);
+
+    // add the workspace members to the graph
+    let mut dag = VisualGraph::new(Orientation::TopToBottom);
+
+    // Define the node styles:
+    let sz = Point::new(100., 100.);
+
+    let mut mapping = HashMap::new();
+
+    for package in nodes {
+        let mut look = StyleAttr::simple();
+        if let Some(status) = statuses.get(&package.id) {
+            look.fill_color = Some(Color::fast(status.color()));
+        }
+
+        let id = dag.add_node(Element::create(
+            ShapeKind::new_box(&format!("{}\nv{}", package.name, package.version)),
+            look,
+            Orientation::TopToBottom,
+            sz,
+        ));
+        mapping.insert(&package.id, id);
+    }
+
+    for (dep, package, status) in edges {
+        let mut look = StyleAttr::simple();
+        if let Some(color) = edge_color(status) {
+            look.line_color = Color::fast(color);
+        }
+
+        // Add an edge between the nodes.
+        let arrow = Arrow::new(
+            LineEndKind::None,
+            LineEndKind::Arrow,
+            LineStyleKind::Normal,
+            &dep.version.to_string(),
+            &look,
+            &None,
+            &None,
+        );
+        dag.add_edge(arrow, mapping[&dep.id], mapping[&package.id]);
+    }
+
     // Render the nodes to some rendering backend.
     let mut svg = SVGWriter::new();
     dag.do_it(false, false, false, &mut svg);
 
-    // Save the output.
-    let _ = save_to_file("graph.svg", &svg.finalize());
+    svg.finalize()
+}
+
+/// The same graph as Graphviz DOT, for anyone who'd rather lay it out with their own tools
+pub fn write_crate_dot(graph: &CrateGraph, statuses: &HashMap<PackageId, ReleaseStatus>) -> String {
+    let (nodes, edges) = graph_elements(graph);
+
+    let mut dot = String::from("digraph workspace {\n    node [shape=box, style=filled];\n");
+
+    for package in nodes {
+        let fill = statuses
+            .get(&package.id)
+            .map(|status| status.color())
+            .unwrap_or("white");
+
+        dot += &format!(
+            "    \"{}\" [label=\"{}\\nv{}\", fillcolor=\"{fill}\"];\n",
+            package.name, package.name, package.version
+        );
+    }
+
+    for (dep, package, status) in edges {
+        let color = edge_color(status).unwrap_or("black");
+
+        dot += &format!(
+            "    \"{}\" -> \"{}\" [label=\"{}\", color=\"{color}\"];\n",
+            dep.name, package.name, dep.version
+        );
+    }
+
+    dot + "}\n"
 }
@@ -0,0 +1,173 @@
+//! Polling the workspace's manifests, so edits made in an editor or by a bump show up
+//!
+//! This stats a few dozen files once a second rather than using a filesystem watcher, which keeps
+//! us off platform event APIs and their quirks with editors that save by renaming. To catch new
+//! members, the snapshot also covers the manifest each member glob match would have, so adding
+//! `crates/foo/Cargo.toml` under a `crates/*` member is noticed even before it's a package.
+use crate::cargo_util::workspace_member_manifests;
+use crate::config::CONFIG_FILE;
+use crate::git;
+use crate::state::{CrateGraph, WorkspaceOptions};
+use cargo_metadata::{
+    camino::{Utf8Path, Utf8PathBuf},
+    Metadata, PackageId,
+};
+use dioxus::prelude::*;
+use std::{collections::HashSet, rc::Rc, time::Duration, time::SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Modification times of the files a graph was built from
+#[derive(Clone, PartialEq, Debug)]
+pub struct Snapshot(Vec<(Utf8PathBuf, Option<SystemTime>)>);
+
+impl Snapshot {
+    /// Every member's Cargo.toml, wherever the member globs would put a new one, plus the root
+    /// manifest, the lockfile and our config
+    pub fn take(meta: &Metadata) -> Self {
+        let root = &meta.workspace_root;
+        let mut files = meta
+            .workspace_packages()
+            .iter()
+            .map(|package| package.manifest_path.clone())
+            .collect::<Vec<_>>();
+
+        files.extend(
+            workspace_member_manifests(root.as_std_path())
+                .into_iter()
+                .filter_map(|path| Utf8PathBuf::from_path_buf(path).ok()),
+        );
+        files.push(root.join("Cargo.toml"));
+        files.push(root.join("Cargo.lock"));
+        files.push(root.join(CONFIG_FILE));
+        files.sort();
+        files.dedup();
+
+        Self(
+            files
+                .into_iter()
+                .map(|path| {
+                    let modified = std::fs::metadata(&path)
+                        .and_then(|meta| meta.modified())
+                        .ok();
+                    (path, modified)
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Rebuild the graph whenever one of its files changes
+///
+/// Only the graph is replaced, so selections and each row's state stay as they were. Ignores are
+/// persisted in the workspace config, so the new graph's defaults carry them over.
+///
+/// Commits and source edits leave the manifests alone, so the workspace's `git status` is polled
+/// too, and `set_working_tree` is handed a new [`WORKING_TREE`](crate::state::WORKING_TREE) value
+/// whenever it changes.
+pub async fn watch_workspace(
+    graph: UseState<CrateGraph>,
+    ignored_crates: UseState<HashSet<PackageId>>,
+    set_working_tree: Rc<dyn Fn(usize)>,
+) {
+    let mut last = Snapshot::take(&graph.current().meta);
+    let mut last_status = None;
+    let mut status_changes = 0;
+
+    loop {
+        tokio::time::sleep(POLL_INTERVAL).await;
+
+        let root = graph.current().meta.workspace_root.clone();
+        let status = git::status(&root, Utf8Path::new(""), &[]).await.ok();
+        if status != last_status {
+            last_status = status;
+            status_changes += 1;
+            set_working_tree(status_changes);
+        }
+
+        let current = graph.current();
+        let snapshot = Snapshot::take(&current.meta);
+        if snapshot == last {
+            continue;
+        }
+
+        match reload_graph(&current).await {
+            Ok(new_graph) => {
+                last = Snapshot::take(&new_graph.meta);
+                ignored_crates.set(new_graph.default_ignored());
+                graph.set(new_graph);
+            }
+            Err(err) => {
+                // Likely a half-written manifest, so wait for the next change and try again
+                println!("Failed to reload workspace: {err}");
+                last = snapshot;
+            }
+        }
+    }
+}
+
+/// Build a graph off the UI thread, since reading the workspace can mean running `cargo metadata`
+pub async fn load_graph(options: WorkspaceOptions) -> anyhow::Result<CrateGraph> {
+    tokio::task::spawn_blocking(move || CrateGraph::load(options))
+        .await
+        .unwrap_or_else(|err| Err(err.into()))
+}
+
+/// [`CrateGraph::reload`], off the UI thread
+async fn reload_graph(graph: &CrateGraph) -> anyhow::Result<CrateGraph> {
+    let old = graph.clone();
+
+    tokio::task::spawn_blocking(move || old.reload())
+        .await
+        .unwrap_or_else(|err| Err(err.into()))
+}
+
+/// Read the workspace again after we've written to it, the same way the watcher does, rather than
+/// waiting for the next poll
+pub async fn reload_workspace(
+    graph: &UseState<CrateGraph>,
+    ignored_crates: &UseState<HashSet<PackageId>>,
+) {
+    match reload_graph(&graph.current()).await {
+        Ok(new_graph) => {
+            ignored_crates.set(new_graph.default_ignored());
+            graph.set(new_graph);
+        }
+        Err(err) => println!("Failed to reload workspace: {err}"),
+    }
+}
+
+/// Swap in a graph with the resolved versions of third-party dependencies, once cargo's resolver
+/// has worked them out. Graphs are read from the manifests first, so this runs after each load
+///
+/// Older cargos give the packages different ids, so anything ignored in the meantime is carried
+/// over by name, along with the config it was saved to. If the workspace changes while cargo
+/// runs, the result is stale and the resolve starts over.
+pub async fn resolve_dependencies(
+    graph: UseState<CrateGraph>,
+    ignored_crates: UseState<HashSet<PackageId>>,
+) {
+    loop {
+        let before = Snapshot::take(&graph.current().meta);
+        let mut options = graph.current().options.clone();
+        options.resolve = true;
+
+        let mut resolved = match load_graph(options).await {
+            Ok(resolved) => resolved,
+            Err(err) => {
+                println!("Failed to resolve workspace dependencies: {err}");
+                return;
+            }
+        };
+
+        let current = graph.current();
+        if Snapshot::take(&current.meta) != before {
+            continue;
+        }
+
+        resolved.config = current.config.clone();
+        ignored_crates.set(resolved.same_crates(&current, &ignored_crates.current()));
+        graph.set(resolved);
+        return;
+    }
+}